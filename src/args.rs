@@ -2,7 +2,9 @@
 //! Config structure.
 
 use crate::{
+    bookmarks,
     config::{self, Config},
+    export::Format,
     ui::Mode,
 };
 use std::{error::Error, fmt, result::Result};
@@ -45,6 +47,13 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
     let mut set_cfg = false;
     let mut cfg = config::default();
 
+    // phetch bookmarks SUBCOMMAND
+    if let Some("bookmarks") = args.first().map(|a| a.as_ref()) {
+        cfg.mode = Mode::Bookmarks;
+        cfg.bookmarks = Some(parse_bookmarks(&args[1..])?);
+        return Ok(cfg);
+    }
+
     // check for config to load / not load first
     let mut iter = args.iter();
     while let Some(arg) = iter.next() {
@@ -124,7 +133,107 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
                 }
             }
             "-p" | "--print" | "-print" => cfg.mode = Mode::Print,
+            "--dump" | "-dump" => {
+                if args.len() > 1 {
+                    cfg.mode = Mode::Print;
+                    cfg.format = Some(Format::Dump);
+                } else {
+                    return Err(ArgError::new("--dump needs gopher-url"));
+                }
+            }
+            "--format" | "-format" => {
+                cfg.format = Some(
+                    iter.next()
+                        .and_then(|f| Format::from_name(f.as_ref()))
+                        .ok_or_else(|| {
+                            ArgError::new(
+                                "--format needs json, tsv, markdown, html, dump, or plain",
+                            )
+                        })?,
+                );
+            }
+            "--lint" | "-lint" => {
+                if args.len() > 1 {
+                    cfg.mode = Mode::Lint;
+                } else {
+                    return Err(ArgError::new("--lint needs gopher-url or file"));
+                }
+            }
+            "--download" | "-download" => {
+                cfg.mode = Mode::Download;
+                match iter.next() {
+                    Some(url) if !url.as_ref().starts_with('-') => {
+                        got_url = true;
+                        cfg.start = url.as_ref().trim().into();
+                    }
+                    _ => return Err(ArgError::new("--download needs gopher-url")),
+                }
+            }
+            "--batch" | "-batch" => {
+                cfg.mode = Mode::Batch;
+                let mut peek = iter.clone();
+                match peek.next().map(|a| a.as_ref()) {
+                    Some(file) if file == "-" || !file.starts_with('-') => {
+                        got_url = true;
+                        cfg.start = file.into();
+                        iter = peek;
+                    }
+                    _ => {}
+                }
+            }
+            "--jobs" | "-jobs" => {
+                cfg.jobs = iter
+                    .next()
+                    .and_then(|j| j.as_ref().parse().ok())
+                    .filter(|j| *j > 0)
+                    .ok_or_else(|| ArgError::new("--jobs needs a number above 0"))?;
+            }
+            "--check-links" | "-check-links" => {
+                if args.len() > 1 {
+                    cfg.mode = Mode::CheckLinks;
+                } else {
+                    return Err(ArgError::new("--check-links needs gopher-url or file"));
+                }
+            }
+            "--mirror" | "-mirror" => {
+                if args.len() > 1 {
+                    cfg.mode = Mode::Mirror;
+                } else {
+                    return Err(ArgError::new("--mirror needs gopher-url"));
+                }
+            }
+            "--depth" | "-depth" => {
+                cfg.depth = iter
+                    .next()
+                    .and_then(|d| d.as_ref().parse().ok())
+                    .ok_or_else(|| ArgError::new("--depth needs a number"))?;
+            }
+            "--prefix" | "-prefix" => cfg.prefix = true,
+            "--output" | "-output" => match iter.next() {
+                Some(out) if out.as_ref() == "-" || !out.as_ref().starts_with('-') => {
+                    cfg.output = Some(out.as_ref().into());
+                }
+                _ => return Err(ArgError::new("--output needs a path")),
+            },
+            "--serve" | "-serve" => {
+                cfg.mode = Mode::Serve;
+                match iter.next() {
+                    Some(dir) if !dir.as_ref().starts_with('-') => {
+                        got_url = true;
+                        cfg.start = dir.as_ref().into();
+                    }
+                    _ => return Err(ArgError::new("--serve needs a directory")),
+                }
+            }
+            "--port" | "-port" => {
+                cfg.port = iter
+                    .next()
+                    .and_then(|p| p.as_ref().parse().ok())
+                    .ok_or_else(|| ArgError::new("--port needs a port number"))?;
+            }
             "-l" | "--local" | "-local" => cfg.start = "gopher://127.0.0.1:7070".into(),
+            "-P" | "--private" | "-private" => cfg.private = true,
+            "--restore" | "-restore" => cfg.restore = true,
             "-C" | "--no-config" | "-no-config" => {}
             "-c" | "--config" | "-config" => {}
             arg if arg.starts_with("--config=") || arg.starts_with("-config=") => {}
@@ -169,26 +278,98 @@ pub fn parse<T: AsRef<str>>(args: &[T]) -> Result<Config, ArgError> {
                     return Err(ArgError::new(format!("unknown argument: {}", arg)));
                 } else {
                     got_url = true;
+                    cfg.start_given = true;
                     cfg.start = arg.trim().into();
                 }
             }
         }
     }
 
+    // --batch with no file reads URLs from stdin
+    if cfg.mode == Mode::Batch && !got_url {
+        cfg.start = "-".into();
+    }
+
     if cfg.tor && cfg.tls {
         return Err(ArgError::new("can't set both --tor and --tls"));
     }
 
     #[cfg(not(test))]
     {
-        if !atty::is(atty::Stream::Stdout) && cfg.mode != Mode::Raw {
+        if !atty::is(atty::Stream::Stdout) && (cfg.mode == Mode::Run || cfg.mode == Mode::Print) {
             cfg.mode = Mode::NoTTY;
         }
     }
 
+    if cfg.format.is_some()
+        && cfg.mode != Mode::Print
+        && cfg.mode != Mode::NoTTY
+        && cfg.mode != Mode::Raw
+    {
+        return Err(ArgError::new("--format only works with --print or --raw"));
+    }
+
     Ok(cfg)
 }
 
+/// Parse the arguments to `phetch bookmarks`.
+fn parse_bookmarks<T: AsRef<str>>(args: &[T]) -> Result<bookmarks::Command, ArgError> {
+    use bookmarks::Command;
+    let args = args.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
+    let num = |arg: Option<&&str>| arg.and_then(|n| n.parse::<usize>().ok()).filter(|n| *n > 0);
+    let format = |arg: Option<&&str>| match arg {
+        Some(name) => bookmarks::Format::from_name(name).map(Some).ok_or_else(|| {
+            ArgError::new(format!(
+                "unknown bookmarks format: {} (try gophermap, vf1, lynx, netscape, or urls)",
+                name
+            ))
+        }),
+        None => Ok(None),
+    };
+
+    let (cmd, max) = match args.first() {
+        None | Some(&"list") => (Command::List, 1),
+        Some(&"add") => match args.get(1) {
+            Some(url) => {
+                let label = if args.len() > 2 {
+                    Some(args[2..].join(" "))
+                } else {
+                    None
+                };
+                (Command::Add(url.to_string(), label), args.len())
+            }
+            None => return Err(ArgError::new("bookmarks add needs a URL")),
+        },
+        Some(&"rm") => match num(args.get(1)) {
+            Some(n) => (Command::Remove(n), 2),
+            None => return Err(ArgError::new("bookmarks rm needs a number")),
+        },
+        Some(&"mv") => match (num(args.get(1)), num(args.get(2))) {
+            (Some(from), Some(to)) => (Command::Move(from, to), 3),
+            _ => return Err(ArgError::new("bookmarks mv needs two numbers")),
+        },
+        Some(&"export") => {
+            let fmt = format(args.get(1))?.unwrap_or(bookmarks::Format::Gophermap);
+            (Command::Export(fmt), 2)
+        }
+        Some(&"import") => match args.get(1) {
+            Some(file) => (Command::Import(file.to_string(), format(args.get(2))?), 3),
+            None => return Err(ArgError::new("bookmarks import needs a file")),
+        },
+        Some(cmd) => {
+            return Err(ArgError::new(format!(
+                "unknown bookmarks command: {} (try list, add, rm, mv, export, or import)",
+                cmd
+            )))
+        }
+    };
+
+    match args.get(max) {
+        Some(arg) => Err(ArgError::new(format!("unknown argument: {}", arg))),
+        None => Ok(cmd),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -247,6 +428,232 @@ mod tests {
         assert_eq!(cfg.mode, Mode::Print);
     }
 
+    #[test]
+    fn test_lint() {
+        let cfg = parse(&["--lint", "gophermap"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Lint);
+        assert_eq!(cfg.start, "gophermap");
+
+        let err = parse(&["--lint"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--lint needs gopher-url or file");
+    }
+
+    #[test]
+    fn test_private() {
+        assert!(!parse(&["sdf.org"]).unwrap().private);
+        let cfg = parse(&["-P", "sdf.org"]).expect("should work");
+        assert!(cfg.private);
+        assert_eq!(cfg.start, "sdf.org");
+        assert!(parse(&["--private"]).unwrap().private);
+    }
+
+    #[test]
+    fn test_restore() {
+        let cfg = parse(&["--restore"]).expect("should work");
+        assert!(cfg.restore);
+        assert!(!cfg.start_given);
+        let cfg = parse(&["--restore", "sdf.org"]).expect("should work");
+        assert!(cfg.start_given);
+    }
+
+    #[test]
+    fn test_serve() {
+        let cfg = parse(&["--serve", "."]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Serve);
+        assert_eq!(cfg.start, ".");
+        assert_eq!(cfg.port, 7070);
+
+        let cfg = parse(&["--serve", "gopherhole", "--port", "7071"]).expect("should work");
+        assert_eq!(cfg.start, "gopherhole");
+        assert_eq!(cfg.port, 7071);
+
+        let err = parse(&["--serve"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--serve needs a directory");
+        let err = parse(&["--serve", ".", "--port", "gopher"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--port needs a port number");
+    }
+
+    #[test]
+    fn test_format() {
+        let cfg = parse(&["-p", "sdf.org", "--format", "json"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Print);
+        assert_eq!(cfg.format, Some(Format::Json));
+
+        let cfg = parse(&["--format", "tsv", "-r", "sdf.org"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Raw);
+        assert_eq!(cfg.format, Some(Format::Tsv));
+
+        let cfg = parse(&["--raw", "--format", "json", "sdf.org"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Raw);
+        assert_eq!(cfg.format, Some(Format::Json));
+
+        let err = parse(&["-p", "sdf.org", "--format", "xml"]).expect_err("should fail");
+        assert_eq!(
+            err.to_string(),
+            "--format needs json, tsv, markdown, html, dump, or plain"
+        );
+        let err = parse(&["sdf.org", "--format", "json"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--format only works with --print or --raw");
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        use crate::bookmarks::{Command, Format};
+        let cmd = |args: &[&str]| parse(args).expect("should work").bookmarks;
+
+        let cfg = parse(&["bookmarks"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Bookmarks);
+        assert_eq!(cfg.bookmarks, Some(Command::List));
+        assert_eq!(cmd(&["bookmarks", "list"]), Some(Command::List));
+        assert_eq!(
+            cmd(&["bookmarks", "add", "sdf.org"]),
+            Some(Command::Add("sdf.org".into(), None))
+        );
+        assert_eq!(
+            cmd(&["bookmarks", "add", "sdf.org", "Super", "Dimension"]),
+            Some(Command::Add(
+                "sdf.org".into(),
+                Some("Super Dimension".into())
+            ))
+        );
+        assert_eq!(cmd(&["bookmarks", "rm", "3"]), Some(Command::Remove(3)));
+        assert_eq!(
+            cmd(&["bookmarks", "mv", "3", "1"]),
+            Some(Command::Move(3, 1))
+        );
+        assert_eq!(
+            cmd(&["bookmarks", "export"]),
+            Some(Command::Export(Format::Gophermap))
+        );
+        assert_eq!(
+            cmd(&["bookmarks", "export", "netscape"]),
+            Some(Command::Export(Format::Netscape))
+        );
+        assert_eq!(
+            cmd(&["bookmarks", "import", "-"]),
+            Some(Command::Import("-".into(), None))
+        );
+        assert_eq!(
+            cmd(&["bookmarks", "import", "lynx_bookmarks.html", "lynx"]),
+            Some(Command::Import(
+                "lynx_bookmarks.html".into(),
+                Some(Format::Lynx)
+            ))
+        );
+
+        let err = |args: &[&str]| parse(args).expect_err("should fail").to_string();
+        assert_eq!(
+            err(&["bookmarks", "rm", "0"]),
+            "bookmarks rm needs a number"
+        );
+        assert_eq!(
+            err(&["bookmarks", "mv", "1"]),
+            "bookmarks mv needs two numbers"
+        );
+        assert_eq!(err(&["bookmarks", "add"]), "bookmarks add needs a URL");
+        assert_eq!(err(&["bookmarks", "list", "x"]), "unknown argument: x");
+        assert!(err(&["bookmarks", "zap"]).starts_with("unknown bookmarks command: zap"));
+        assert!(err(&["bookmarks", "export", "xbel"]).starts_with("unknown bookmarks format: xbel"));
+
+        // only as the first argument
+        let cfg = parse(&["sdf.org", "bookmarks"]).expect_err("should fail");
+        assert_eq!(cfg.to_string(), "unknown argument: bookmarks");
+    }
+
+    #[test]
+    fn test_batch() {
+        let cfg = parse(&["--batch"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Batch);
+        assert_eq!(cfg.start, "-");
+        assert_eq!(cfg.jobs, 4);
+
+        let cfg =
+            parse(&["--batch", "urls.txt", "--jobs", "8", "--output", "out"]).expect("should work");
+        assert_eq!(cfg.start, "urls.txt");
+        assert_eq!(cfg.jobs, 8);
+        assert_eq!(cfg.output, Some("out".into()));
+
+        let cfg = parse(&["--batch", "--jobs", "2", "urls.txt"]).expect("should work");
+        assert_eq!(cfg.start, "urls.txt");
+        assert_eq!(cfg.jobs, 2);
+
+        let err = parse(&["--batch", "--jobs", "0"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--jobs needs a number above 0");
+    }
+
+    #[test]
+    fn test_download() {
+        let cfg = parse(&["--download", "sdf.org/9/file.zip"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Download);
+        assert_eq!(cfg.start, "sdf.org/9/file.zip");
+        assert_eq!(cfg.output, None);
+
+        let cfg =
+            parse(&["--download", "sdf.org/9/file.zip", "--output", "-"]).expect("should work");
+        assert_eq!(cfg.output, Some("-".into()));
+
+        let cfg =
+            parse(&["--download", "sdf.org/9/a.zip", "--output", "b.zip"]).expect("should work");
+        assert_eq!(cfg.output, Some("b.zip".into()));
+
+        let err = parse(&["--download"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--download needs gopher-url");
+        let err = parse(&["--download", "sdf.org/9/a.zip", "--output"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--output needs a path");
+    }
+
+    #[test]
+    fn test_dump() {
+        let cfg = parse(&["--dump", "sdf.org"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Print);
+        assert_eq!(cfg.format, Some(Format::Dump));
+        assert_eq!(cfg.start, "sdf.org");
+
+        let err = parse(&["--dump"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--dump needs gopher-url");
+    }
+
+    #[test]
+    fn test_check_links() {
+        let cfg = parse(&["--check-links", "sdf.org"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::CheckLinks);
+        assert_eq!(cfg.start, "sdf.org");
+
+        let err = parse(&["--check-links"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--check-links needs gopher-url or file");
+    }
+
+    #[test]
+    fn test_mirror() {
+        let cfg = parse(&["--mirror", "gopher://sdf.org"]).expect("should work");
+        assert_eq!(cfg.mode, Mode::Mirror);
+        assert_eq!(cfg.start, "gopher://sdf.org");
+        assert_eq!(cfg.depth, 3);
+        assert!(!cfg.prefix);
+        assert_eq!(cfg.output, None);
+
+        let cfg = parse(&[
+            "--mirror",
+            "sdf.org/1/users/frog",
+            "--depth",
+            "5",
+            "--prefix",
+            "--output",
+            "frog",
+        ])
+        .expect("should work");
+        assert_eq!(cfg.depth, 5);
+        assert!(cfg.prefix);
+        assert_eq!(cfg.output, Some("frog".into()));
+
+        let err = parse(&["--mirror"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--mirror needs gopher-url");
+        let err = parse(&["--mirror", "sdf.org", "--depth", "all"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--depth needs a number");
+        let err = parse(&["--mirror", "sdf.org", "--output"]).expect_err("should fail");
+        assert_eq!(err.to_string(), "--output needs a path");
+    }
+
     #[test]
     fn test_help() {
         let cfg = parse(&["--help"]).expect("should work");
@@ -0,0 +1,234 @@
+//! `phetch --batch` fetches a list of URLs, one per line, for
+//! scheduled jobs and scripts. Up to `--jobs` URLs are fetched at
+//! once. Responses are written as raw bytes, either to a file per URL
+//! in the `--output` directory or to stdout with a separator line
+//! before each one, always in the order they were listed.
+
+use crate::gopher;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::{BufRead, Result, Write},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+/// Default number of URLs to fetch at once.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// How to run a batch.
+pub struct Options {
+    /// How many URLs to fetch at once.
+    pub jobs: usize,
+    /// Directory to save responses in. Stdout if None.
+    pub output: Option<PathBuf>,
+    /// Use TLS?
+    pub tls: bool,
+    /// Use Tor?
+    pub tor: bool,
+}
+
+/// What happened to a single URL.
+#[derive(Debug)]
+pub struct Outcome {
+    /// The URL from the list.
+    pub url: String,
+    /// Where its response went: a file path, or "-" for stdout.
+    pub dest: String,
+    /// Size of the response, or why we couldn't get it.
+    pub result: std::result::Result<usize, String>,
+}
+
+/// Read a list of URLs. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn read_urls(input: impl BufRead) -> Result<Vec<String>> {
+    let mut urls = vec![];
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            urls.push(line.to_string());
+        }
+    }
+    Ok(urls)
+}
+
+/// Fetch all the URLs, writing responses to `opts.output` or to
+/// `out`. Returns what happened to each, in order.
+pub fn run(urls: Vec<String>, opts: &Options, out: &mut dyn Write) -> Result<Vec<Outcome>> {
+    if let Some(dir) = &opts.output {
+        fs::create_dir_all(dir)?;
+    }
+
+    let total = urls.len();
+    let queue = Arc::new(Mutex::new(
+        urls.iter().cloned().enumerate().rev().collect::<Vec<_>>(),
+    ));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..opts.jobs.max(1).min(total) {
+        let queue = queue.clone();
+        let tx = tx.clone();
+        let (tls, tor) = (opts.tls, opts.tor);
+        thread::spawn(move || loop {
+            let next = queue.lock().ok().and_then(|mut q| q.pop());
+            let (i, url) = match next {
+                Some(next) => next,
+                None => return,
+            };
+            let res = gopher::fetch_url_bytes(&url, tls, tor).map(|(_, body)| body);
+            if tx.send((i, res)).is_err() {
+                return;
+            }
+        });
+    }
+    drop(tx);
+
+    // write responses in list order, as soon as they're ready
+    let mut names = HashSet::new();
+    let mut pending = BTreeMap::new();
+    let mut outcomes = vec![];
+    for (i, res) in rx.iter() {
+        pending.insert(i, res);
+        while let Some(res) = pending.remove(&outcomes.len()) {
+            let url = &urls[outcomes.len()];
+            let dest = match &opts.output {
+                Some(dir) => dir
+                    .join(unique_name(&mut names, &filename(url)))
+                    .to_string_lossy()
+                    .to_string(),
+                None => "-".to_string(),
+            };
+            let result = match res {
+                Ok(body) => write_response(out, url, &dest, &body)
+                    .map(|_| body.len())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            outcomes.push(Outcome {
+                url: url.to_string(),
+                dest,
+                result,
+            });
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Save a response to its file, or to `out` after a separator.
+fn write_response(out: &mut dyn Write, url: &str, dest: &str, body: &[u8]) -> Result<()> {
+    if dest != "-" {
+        return fs::write(dest, body);
+    }
+    writeln!(out, "==> {} <==", url)?;
+    out.write_all(body)?;
+    if !body.is_empty() && !body.ends_with(b"\n") {
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// File name to save a URL's response as, ex:
+/// "gopher://sdf.org/1/users" => "sdf.org_1_users"
+pub fn filename(url: &str) -> String {
+    let name = url
+        .trim_start_matches("gopher://")
+        .trim_end_matches('/')
+        .replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+            "_",
+        );
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "index".into()
+    } else {
+        name.into()
+    }
+}
+
+/// Add a number to a file name if it's already been used.
+fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let mut unique = name.to_string();
+    let mut n = 1;
+    while !names.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{}-{}", name, n);
+    }
+    unique
+}
+
+/// A line for each URL, then a count of how many failed.
+pub fn summary(outcomes: &[Outcome]) -> String {
+    let mut out = String::new();
+    let mut failed = 0;
+    for outcome in outcomes {
+        match &outcome.result {
+            Ok(bytes) => out.push_str(&format!(
+                "ok    {}  {} -> {}\n",
+                crate::utils::human_bytes(*bytes),
+                outcome.url,
+                outcome.dest
+            )),
+            Err(e) => {
+                failed += 1;
+                out.push_str(&format!("fail  {}  {}\n", outcome.url, e));
+            }
+        }
+    }
+    out.push_str(&format!(
+        "{} fetched, {} failed\n",
+        outcomes.len() - failed,
+        failed
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_urls() {
+        let list = "# nightly\nsdf.org\n\n  gopher://bitreich.org/1/lawn  \n#gone.org\n";
+        assert_eq!(
+            read_urls(list.as_bytes()).unwrap(),
+            vec!["sdf.org", "gopher://bitreich.org/1/lawn"]
+        );
+    }
+
+    #[test]
+    fn test_filename() {
+        assert_eq!(filename("gopher://sdf.org/1/users/"), "sdf.org_1_users");
+        assert_eq!(
+            filename("gopher://[::1]:7070/0/a b.txt"),
+            "___1__7070_0_a_b.txt"
+        );
+        assert_eq!(filename("gopher://../../etc"), "_.._etc");
+        assert_eq!(filename("gopher://"), "index");
+
+        let mut names = HashSet::new();
+        assert_eq!(unique_name(&mut names, "sdf.org"), "sdf.org");
+        assert_eq!(unique_name(&mut names, "sdf.org"), "sdf.org-2");
+        assert_eq!(unique_name(&mut names, "sdf.org"), "sdf.org-3");
+    }
+
+    #[test]
+    fn test_summary() {
+        let outcomes = vec![
+            Outcome {
+                url: "sdf.org".into(),
+                dest: "-".into(),
+                result: Ok(1200),
+            },
+            Outcome {
+                url: "gone.org".into(),
+                dest: "-".into(),
+                result: Err("Connection refused".into()),
+            },
+        ];
+        assert_eq!(
+            summary(&outcomes),
+            "ok    1Kb  sdf.org -> -\nfail  gone.org  Connection refused\n1 fetched, 1 failed\n"
+        );
+    }
+}
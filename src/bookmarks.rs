@@ -1,13 +1,223 @@
 //! Bookmarks are enabled if you create a ~/.config/phetch/ directory
 //! manually. They are stored as a simple Gophermap, `BOOKMARKS_FILE`,
 //! in that directory.
+//!
+//! Bookmarks are numbered from 1 in the order they appear in the
+//! file, the same as the links on the bookmarks page. Only link lines
+//! count, so info lines added by hand are kept when bookmarks are
+//! removed or moved around.
+//!
+//! Bookmarks can also be kept in folders. Each folder is its own
+//! Gophermap in `FOLDERS_DIR`, shown as a submenu of the bookmarks
+//! page at `gopher://phetch/1/bookmarks/NAME`. Folders are created
+//! when something is moved into them and removed once they're empty.
 
-use crate::phetchdir;
-use std::io::{Read, Result};
+use crate::{gopher, menu, phetchdir};
+use std::{
+    fs,
+    io::{Read, Result},
+};
+
+mod formats;
+pub use self::formats::Format;
 
 /// Bookmarks only work if you've created a ~/.config/phetch/ manually.
 const BOOKMARKS_FILE: &str = "bookmarks.gph";
 
+/// Directory in the phetchdir where bookmark folders are saved.
+const FOLDERS_DIR: &str = "bookmarks";
+
+/// URL of the bookmarks page.
+pub const URL: &str = "gopher://phetch/1/bookmarks";
+
+/// Things `phetch bookmarks` can do from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Print all bookmarks, numbered.
+    List,
+    /// Add a bookmark: Add(url, label)
+    Add(String, Option<String>),
+    /// Remove a bookmark by number.
+    Remove(usize),
+    /// Move a bookmark: Move(from, to)
+    Move(usize, usize),
+    /// Print all bookmarks, including folders.
+    Export(Format),
+    /// Add the bookmarks in a file, or stdin if it's "-". The format
+    /// is guessed if it's not given: Import(file, format)
+    Import(String, Option<Format>),
+}
+
+/// A single bookmark.
+#[derive(Debug, PartialEq)]
+pub struct Bookmark {
+    /// Text shown for the link.
+    pub label: String,
+    /// Where it goes.
+    pub url: String,
+}
+
+/// The contents of the bookmarks file or a folder, for editing.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    /// Folder these bookmarks are in. None for the main file.
+    folder: Option<String>,
+    /// Lines of the file, without line endings.
+    lines: Vec<String>,
+}
+
+impl Bookmarks {
+    /// Parse a bookmarks Gophermap.
+    pub fn parse(raw: &str) -> Bookmarks {
+        Bookmarks {
+            folder: None,
+            lines: raw
+                .lines()
+                .map(|l| l.trim_end_matches('\r'))
+                .take_while(|l| *l != ".")
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// Load the bookmarks file. It's fine if it doesn't exist yet,
+    /// but the phetchdir has to.
+    pub fn load() -> Result<Bookmarks> {
+        Bookmarks::load_folder(None)
+    }
+
+    /// Load the bookmarks in a folder, or the main file if None.
+    pub fn load_folder(folder: Option<&str>) -> Result<Bookmarks> {
+        phetchdir::path()?;
+        if let Some(name) = folder {
+            folder_name(name)?;
+        }
+        let file = file_for(folder);
+        let mut bookmarks = if phetchdir::exists(&file) {
+            let mut raw = String::new();
+            phetchdir::load(&file)?.read_to_string(&mut raw)?;
+            Bookmarks::parse(&raw)
+        } else {
+            Bookmarks::default()
+        };
+        bookmarks.folder = folder.map(String::from);
+        Ok(bookmarks)
+    }
+
+    /// Write the bookmarks back to disk. Folders with no bookmarks
+    /// left are removed.
+    pub fn save(&self) -> Result<()> {
+        let file = file_for(self.folder());
+        if self.folder.is_some() && self.is_empty() {
+            let path = phetchdir::path()?.join(file);
+            return if path.exists() {
+                fs::remove_file(path)
+            } else {
+                Ok(())
+            };
+        }
+        phetchdir::save(&file, &self.to_string())
+    }
+
+    /// Folder these bookmarks are in. None for the main file.
+    pub fn folder(&self) -> Option<&str> {
+        self.folder.as_deref()
+    }
+
+    /// All the bookmarks, in order.
+    pub fn list(&self) -> Vec<Bookmark> {
+        self.lines
+            .iter()
+            .filter_map(|line| match menu::parse_line(0, line) {
+                Some(l) if l.typ.is_link() => Some(Bookmark {
+                    label: l.text(line).to_string(),
+                    url: l.url(line),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Number of bookmarks.
+    pub fn len(&self) -> usize {
+        self.links().count()
+    }
+
+    /// No bookmarks at all?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a bookmark to the end.
+    pub fn add(&mut self, label: &str, url: &str) {
+        self.lines.push(phetchdir::menu_line(label, url));
+    }
+
+    /// Remove bookmark number `n`, starting from 1.
+    pub fn remove(&mut self, n: usize) -> Result<Bookmark> {
+        let i = self.index(n)?;
+        let bookmark = self.list().remove(n - 1);
+        self.lines.remove(i);
+        Ok(bookmark)
+    }
+
+    /// Change the label of bookmark number `n`.
+    pub fn rename(&mut self, n: usize, label: &str) -> Result<()> {
+        let i = self.index(n)?;
+        let line = &self.lines[i];
+        let typ = line.chars().next().unwrap_or('1');
+        let rest = line.find('\t').map(|t| &line[t..]).unwrap_or("");
+        self.lines[i] = format!("{}{}{}", typ, label.replace('\t', " "), rest);
+        Ok(())
+    }
+
+    /// Move bookmark number `n` to the end of another folder.
+    pub fn move_to_folder(&mut self, n: usize, dest: &mut Bookmarks) -> Result<Bookmark> {
+        if self.folder == dest.folder {
+            return Err(error!("Already in that folder"));
+        }
+        let i = self.index(n)?;
+        let bookmark = self.list().remove(n - 1);
+        dest.lines.push(self.lines.remove(i));
+        Ok(bookmark)
+    }
+
+    /// Move bookmark number `from` so it becomes number `to`.
+    pub fn move_to(&mut self, from: usize, to: usize) -> Result<()> {
+        let i = self.index(from)?;
+        let j = self.index(to)?;
+        let line = self.lines.remove(i);
+        self.lines.insert(j, line);
+        Ok(())
+    }
+
+    /// Index into `lines` of bookmark number `n`.
+    fn index(&self, n: usize) -> Result<usize> {
+        self.links()
+            .nth(n.wrapping_sub(1))
+            .ok_or_else(|| error!("No bookmark #{}", n))
+    }
+
+    /// Indexes into `lines` of the link lines.
+    fn links(&self) -> impl Iterator<Item = usize> + '_ {
+        self.lines.iter().enumerate().filter_map(|(i, line)| {
+            menu::parse_line(0, line)
+                .filter(|l| l.typ.is_link())
+                .map(|_| i)
+        })
+    }
+}
+
+impl std::fmt::Display for Bookmarks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}\r\n", line)?;
+        }
+        Ok(())
+    }
+}
+
 macro_rules! dir_missing_fmt {
     () => {
         "i\r\ni\r
@@ -18,22 +228,38 @@ i\r\nimkdir -p {dir}"
     };
 }
 
-/// Get all bookmarks in Gophermap format.
-pub fn as_raw_menu() -> String {
+/// Get all bookmarks in a folder, or the main bookmarks file if
+/// None, in Gophermap format.
+pub fn as_raw_menu(folder: Option<&str>) -> String {
     let path = phetchdir::path();
     if let Err(e) = path {
         return format!(dir_missing_fmt!(), error = e, dir = phetchdir::DIR);
     }
 
-    let mut out = format!("i{}{}:\r\ni\r\n", phetchdir::DIR, BOOKMARKS_FILE);
+    if let Some(Err(e)) = folder.map(folder_name) {
+        return format!("3{}\r\n", e);
+    }
+
+    let file = file_for(folder);
+    let mut out = format!("i{}{}:\r\ni\r\n", phetchdir::DIR, file);
+    let nav = nav_links(folder);
+    if !nav.is_empty() {
+        out.push_str(&nav.join("\r\n"));
+        out.push_str("\r\ni\r\n");
+    }
 
-    let path = path.unwrap().join(BOOKMARKS_FILE);
+    let path = path.unwrap().join(&file);
     if !path.exists() {
-        out.push_str("iNo bookmarks yet.\r\ni\r\niUse <ctrl-s> to bookmark a page.\r\n");
+        match folder {
+            Some(name) => out.push_str(&format!("iNo bookmarks in {}.\r\n", name)),
+            None => {
+                out.push_str("iNo bookmarks yet.\r\ni\r\niUse <ctrl-s> to bookmark a page.\r\n")
+            }
+        }
         return out;
     }
 
-    match phetchdir::load(BOOKMARKS_FILE) {
+    match phetchdir::load(&file) {
         Ok(mut reader) => {
             if let Err(e) = reader.read_to_string(&mut out) {
                 out = format!("3{}", e);
@@ -45,13 +271,223 @@ pub fn as_raw_menu() -> String {
     out
 }
 
+/// Links shown above the bookmarks on their page: the folders on the
+/// main page, or a way back to it from a folder. The UI needs to skip
+/// these when numbering bookmarks.
+pub fn nav_links(folder: Option<&str>) -> Vec<String> {
+    match folder {
+        Some(_) => vec![format!("1<- all bookmarks\t/{}\tphetch", FOLDERS_DIR)],
+        None => folders()
+            .iter()
+            .map(|name| format!("1{}/\t/{}/{}\tphetch", name, FOLDERS_DIR, name))
+            .collect(),
+    }
+}
+
+/// Names of all the bookmark folders, sorted.
+pub fn folders() -> Vec<String> {
+    let dir = match phetchdir::path() {
+        Ok(path) => path.join(FOLDERS_DIR),
+        Err(_) => return vec![],
+    };
+    let mut names = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    name.strip_suffix(".gph").map(String::from)
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort_by_key(|n| n.to_lowercase());
+    names
+}
+
+/// Check a folder name typed in by the user. Returns it trimmed.
+pub fn folder_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+    {
+        Err(error!("Bad folder name: {:?}", name))
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+/// If the URL is a bookmarks page, which folder it shows: Some(None)
+/// for the main page, Some(Some(name)) for a folder.
+pub fn folder_for_url(url: &str) -> Option<Option<String>> {
+    let rest = url.strip_prefix(URL)?.trim_end_matches('/');
+    if rest.is_empty() {
+        Some(None)
+    } else {
+        rest.strip_prefix('/').map(|name| Some(name.to_string()))
+    }
+}
+
+/// URL of the page for a folder, or the main bookmarks page if None.
+pub fn url_for(folder: Option<&str>) -> String {
+    match folder {
+        Some(name) => format!("{}/{}", URL, name),
+        None => URL.to_string(),
+    }
+}
+
+/// File in the phetchdir a folder is saved in.
+fn file_for(folder: Option<&str>) -> String {
+    match folder {
+        Some(name) => format!("{}/{}.gph", FOLDERS_DIR, name),
+        None => BOOKMARKS_FILE.to_string(),
+    }
+}
+
+/// The main bookmarks followed by each folder.
+pub fn load_all() -> Result<Vec<Bookmarks>> {
+    let mut all = vec![Bookmarks::load()?];
+    for name in folders() {
+        all.push(Bookmarks::load_folder(Some(&name))?);
+    }
+    Ok(all)
+}
+
+/// All bookmarks, including folders, in another client's format.
+pub fn export(format: Format) -> Result<String> {
+    Ok(formats::render(&load_all()?, format))
+}
+
+/// Add the bookmarks in another client's file that aren't bookmarked
+/// yet, anywhere. Returns how many were added.
+pub fn import(raw: &str, format: Option<Format>) -> Result<usize> {
+    let format = format.unwrap_or_else(|| Format::detect(raw));
+    // compare URLs the way they'll be saved, ex: sdf.org is gopher://sdf.org
+    let normal = |url: &str| gopher::parse_url(url).to_string();
+    let mut urls = load_all()?
+        .iter()
+        .flat_map(|marks| marks.list())
+        .map(|b| normal(&b.url))
+        .collect::<Vec<_>>();
+    let mut added = 0;
+    for b in formats::parse(raw, format) {
+        let url = normal(&b.url);
+        if !urls.contains(&url) {
+            save(&b.label, &b.url)?;
+            urls.push(url);
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
 /// Save a single bookmark entry.
 pub fn save(label: &str, url: &str) -> Result<()> {
-    phetchdir::append(
-        BOOKMARKS_FILE,
-        label
-            .trim_start_matches("gopher://")
-            .trim_end_matches("/1/"),
-        url,
-    )
+    phetchdir::append(BOOKMARKS_FILE, &label.replace('\t', " "), url)
+}
+
+/// Label for a bookmark that wasn't given one: its URL, minus the
+/// boring parts.
+pub fn default_label(url: &str) -> String {
+    url.trim_start_matches("gopher://")
+        .trim_end_matches("/1/")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &str = "iMy sites\r\n1SDF\t/\tsdf.org\t70\r\n\
+                       1Lawn\t/lawn\tbitreich.org\t70\r\n\
+                       iAfter\r\n0Tips\t/tips.txt\tsdf.org\t70\r\n.\r\n";
+
+    #[test]
+    fn test_list() {
+        let b = Bookmarks::parse(RAW);
+        assert_eq!(b.len(), 3);
+        assert_eq!(
+            b.list()[1],
+            Bookmark {
+                label: "Lawn".into(),
+                url: "gopher://bitreich.org/1/lawn".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut b = Bookmarks::parse(RAW);
+        assert_eq!(b.remove(2).unwrap().label, "Lawn");
+        assert_eq!(
+            b.to_string(),
+            "iMy sites\r\n1SDF\t/\tsdf.org\t70\r\niAfter\r\n0Tips\t/tips.txt\tsdf.org\t70\r\n"
+        );
+        assert!(b.remove(3).is_err());
+        assert!(b.remove(0).is_err());
+    }
+
+    #[test]
+    fn test_move_to() {
+        let mut b = Bookmarks::parse(RAW);
+        b.move_to(3, 1).unwrap();
+        let labels = b.list().into_iter().map(|b| b.label).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["Tips", "SDF", "Lawn"]);
+        b.move_to(1, 3).unwrap();
+        let labels = b.list().into_iter().map(|b| b.label).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["SDF", "Lawn", "Tips"]);
+        assert!(b.move_to(1, 4).is_err());
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut b = Bookmarks::parse(RAW);
+        b.rename(3, "Tips\tand tricks").unwrap();
+        assert_eq!(b.lines[4], "0Tips and tricks\t/tips.txt\tsdf.org\t70");
+        assert!(b.rename(4, "Nope").is_err());
+    }
+
+    #[test]
+    fn test_move_to_folder() {
+        let mut b = Bookmarks::parse(RAW);
+        let mut folder = Bookmarks::parse("");
+        folder.folder = Some("Gopher".into());
+        assert_eq!(b.move_to_folder(1, &mut folder).unwrap().label, "SDF");
+        assert_eq!(b.len(), 2);
+        assert_eq!(folder.list()[0].url, "gopher://sdf.org");
+        assert!(b.move_to_folder(1, &mut Bookmarks::parse("")).is_err());
+        assert!(folder.move_to_folder(1, &mut b).is_ok());
+        assert_eq!(b.list()[2].label, "SDF");
+    }
+
+    #[test]
+    fn test_folders() {
+        assert_eq!(folder_for_url(URL), Some(None));
+        assert_eq!(folder_for_url("gopher://phetch/1/bookmarks/"), Some(None));
+        assert_eq!(
+            folder_for_url("gopher://phetch/1/bookmarks/Phlogs"),
+            Some(Some("Phlogs".into()))
+        );
+        assert_eq!(folder_for_url("gopher://phetch/1/bookmarksx"), None);
+        assert_eq!(folder_for_url("gopher://phetch/1/history"), None);
+        assert_eq!(
+            url_for(Some("Phlogs")),
+            "gopher://phetch/1/bookmarks/Phlogs"
+        );
+        assert_eq!(folder_name("  Phlogs ").unwrap(), "Phlogs");
+        assert!(folder_name("../etc").is_err());
+        assert!(folder_name("a/b").is_err());
+        assert!(folder_name(" ").is_err());
+    }
+
+    #[test]
+    fn test_add() {
+        let mut b = Bookmarks::parse(RAW);
+        b.add("Floodgap", "gopher://gopher.floodgap.com/");
+        assert_eq!(b.list()[3].url, "gopher://gopher.floodgap.com");
+        b.add("Web", "https://example.com");
+        assert_eq!(b.lines[6], "hWeb\tURL:https://example.com\t\t");
+        assert_eq!(b.list()[4].url, "https://example.com");
+    }
 }
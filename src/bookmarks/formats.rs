@@ -0,0 +1,246 @@
+//! Bookmark files from other clients, for `phetch bookmarks import`
+//! and `phetch bookmarks export`.
+//!
+//! - lynx keeps an HTML list of links in `~/lynx_bookmarks.html`.
+//! - Netscape bookmark HTML is what Firefox, Chrome, and friends
+//!   import and export.
+//! - VF-1 and sacc keep Gophermap lines, same as phetch.
+//! - Plenty of tools can read or write a plain list of URLs.
+
+use super::{Bookmark, Bookmarks};
+use crate::{export::escape_html, html};
+
+/// A bookmark file format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Gophermap lines: phetch's own format, and VF-1's.
+    Gophermap,
+    /// lynx's bookmark file.
+    Lynx,
+    /// Netscape bookmark HTML.
+    Netscape,
+    /// One URL per line, optionally followed by a label.
+    Urls,
+}
+
+impl Format {
+    /// Format for a name given on the command line.
+    pub fn from_name(name: &str) -> Option<Format> {
+        Some(match name.to_ascii_lowercase().as_ref() {
+            "gophermap" | "gph" | "vf1" | "vf-1" => Format::Gophermap,
+            "lynx" => Format::Lynx,
+            "netscape" | "html" => Format::Netscape,
+            "urls" | "txt" => Format::Urls,
+            _ => return None,
+        })
+    }
+
+    /// Guess the format of a bookmark file from its contents.
+    pub fn detect(raw: &str) -> Format {
+        let lower = raw.to_ascii_lowercase();
+        if lower.contains("<!doctype netscape-bookmark-file") {
+            Format::Netscape
+        } else if lower.contains("<a ") {
+            Format::Lynx
+        } else if raw.lines().any(|l| l.contains('\t')) {
+            Format::Gophermap
+        } else {
+            Format::Urls
+        }
+    }
+}
+
+/// Read the bookmarks in a file. Folders in HTML files are flattened.
+pub(super) fn parse(raw: &str, format: Format) -> Vec<Bookmark> {
+    match format {
+        Format::Gophermap => Bookmarks::parse(raw).list(),
+        Format::Lynx | Format::Netscape => html::links(raw)
+            .into_iter()
+            .filter(|(_, href)| href.contains("://"))
+            .map(|(label, url)| bookmark(label, url))
+            .collect(),
+        Format::Urls => raw
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut parts = line.splitn(2, char::is_whitespace);
+                let url = parts.next().unwrap_or("").to_string();
+                let label = parts.next().unwrap_or("").trim().to_string();
+                bookmark(label, url)
+            })
+            .collect(),
+    }
+}
+
+/// Bookmark with the URL as its label if it doesn't have one.
+fn bookmark(label: String, url: String) -> Bookmark {
+    let label = if label.is_empty() {
+        super::default_label(&url)
+    } else {
+        label
+    };
+    Bookmark { label, url }
+}
+
+/// Write out the main bookmarks and any folders. lynx can read
+/// Netscape bookmark HTML, so that's what it gets.
+pub(super) fn render(folders: &[Bookmarks], format: Format) -> String {
+    match format {
+        Format::Gophermap => gophermap(folders),
+        Format::Lynx | Format::Netscape => netscape(folders),
+        Format::Urls => folders
+            .iter()
+            .flat_map(|marks| marks.list())
+            .map(|b| format!("{} {}\n", b.url, b.label))
+            .collect(),
+    }
+}
+
+/// The Gophermaps as they are, with each folder under its name.
+fn gophermap(folders: &[Bookmarks]) -> String {
+    let mut out = String::new();
+    for marks in folders {
+        if let Some(name) = marks.folder() {
+            out.push_str(&format!("i\r\ni{}/\r\n", name));
+        }
+        out.push_str(&marks.to_string());
+    }
+    out
+}
+
+/// Netscape bookmark HTML, with folders as nested lists.
+fn netscape(folders: &[Bookmarks]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <!-- This is an automatically generated file. -->\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n\
+         <DL><p>\n",
+    );
+    for marks in folders {
+        let indent = if let Some(name) = marks.folder() {
+            out.push_str(&format!(
+                "    <DT><H3>{}</H3>\n    <DL><p>\n",
+                escape_html(name)
+            ));
+            "        "
+        } else {
+            "    "
+        };
+        for b in marks.list() {
+            out.push_str(&format!(
+                "{}<DT><A HREF=\"{}\">{}</A>\n",
+                indent,
+                escape_html(&b.url),
+                escape_html(&b.label)
+            ));
+        }
+        if marks.folder().is_some() {
+            out.push_str("    </DL><p>\n");
+        }
+    }
+    out.push_str("</DL><p>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LYNX: &str = "<head>
+<META http-equiv=\"content-type\" content=\"text/html;charset=utf-8\">
+<title>Bookmark file</title></head>
+     You can delete links using the remove bookmark command.<p>
+<ol>
+<LI><a href=\"gopher://sdf.org/1/\">SDF Public Access UNIX System</a>
+<LI><a href=\"https://lynx.invisible-island.net/\">Lynx &amp; friends</a>
+</ol>
+";
+
+    const NETSCAPE: &str = "<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><H3 ADD_DATE=\"1\">Gopher</H3>
+    <DL><p>
+        <DT><A HREF=\"gopher://bitreich.org/1/lawn\" ADD_DATE=\"1\">Lawn</A>
+    </DL><p>
+    <DT><A HREF=\"place:sort=8\">Recent</A>
+</DL><p>
+";
+
+    fn urls(marks: &[Bookmark]) -> Vec<&str> {
+        marks.iter().map(|b| b.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Format::detect(LYNX), Format::Lynx);
+        assert_eq!(Format::detect(NETSCAPE), Format::Netscape);
+        assert_eq!(Format::detect("1SDF\t/\tsdf.org\t70\n"), Format::Gophermap);
+        assert_eq!(Format::detect("gopher://sdf.org/ SDF\n"), Format::Urls);
+        assert_eq!(Format::from_name("VF-1"), Some(Format::Gophermap));
+        assert_eq!(Format::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_parse_html() {
+        let marks = parse(LYNX, Format::Lynx);
+        assert_eq!(
+            urls(&marks),
+            vec!["gopher://sdf.org/1/", "https://lynx.invisible-island.net/"]
+        );
+        assert_eq!(marks[1].label, "Lynx & friends");
+
+        let marks = parse(NETSCAPE, Format::Netscape);
+        assert_eq!(urls(&marks), vec!["gopher://bitreich.org/1/lawn"]);
+        assert_eq!(marks[0].label, "Lawn");
+    }
+
+    #[test]
+    fn test_parse_non_ascii() {
+        let html = "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+                    <DL><p><DT><H3>Élan</H3>\n\
+                    <DL><p><DT><A HREF=\"gopher://sdf.org/1/café\">Café «gopher»</A>\n\
+                    </DL><p>ünicode\n\
+                    </DL><p>\n";
+        let marks = parse(html, Format::detect(html));
+        assert_eq!(urls(&marks), vec!["gopher://sdf.org/1/café"]);
+        assert_eq!(marks[0].label, "Café «gopher»");
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let vf1 = "1SDF\t/\tsdf.org\t70\n0Tips\t/tips.txt\tsdf.org\t70\n";
+        let marks = parse(vf1, Format::Gophermap);
+        assert_eq!(
+            urls(&marks),
+            vec!["gopher://sdf.org", "gopher://sdf.org/0/tips.txt"]
+        );
+
+        let list = "# mine\ngopher://sdf.org/1/   SDF  UNIX\n\nbitreich.org\n";
+        let marks = parse(list, Format::Urls);
+        assert_eq!(urls(&marks), vec!["gopher://sdf.org/1/", "bitreich.org"]);
+        assert_eq!(marks[0].label, "SDF  UNIX");
+        assert_eq!(marks[1].label, "bitreich.org");
+    }
+
+    #[test]
+    fn test_render() {
+        let mut folder = Bookmarks::parse("iMine\r\n1Lawn\t/lawn\tbitreich.org\t70\r\n");
+        folder.folder = Some("Phlogs & more".into());
+        let folders = vec![Bookmarks::parse("1SDF\t/\tsdf.org\t70\r\n"), folder];
+        assert_eq!(
+            render(&folders, Format::Urls),
+            "gopher://sdf.org SDF\ngopher://bitreich.org/1/lawn Lawn\n"
+        );
+        let html = render(&folders, Format::Netscape);
+        assert!(html.contains("    <DT><A HREF=\"gopher://sdf.org\">SDF</A>\n"));
+        assert!(html.contains("<DT><H3>Phlogs &amp; more</H3>"));
+        assert_eq!(parse(&html, Format::detect(&html)).len(), 2);
+        assert_eq!(
+            render(&folders, Format::Gophermap),
+            "1SDF\t/\tsdf.org\t70\r\ni\r\niPhlogs & more/\r\niMine\r\n1Lawn\t/lawn\tbitreich.org\t70\r\n"
+        );
+    }
+}
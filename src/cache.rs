@@ -0,0 +1,106 @@
+//! An in-memory cache of Gopher responses, keyed by URL. Once it's
+//! full, the least recently used page makes room for the next one,
+//! and pages older than `MAX_AGE` are treated as gone so you never
+//! see a stale copy. Nothing is written to disk.
+
+use std::time::{Duration, Instant};
+
+/// Most pages to keep.
+pub const DEFAULT_SIZE: usize = 100;
+
+/// How long a page stays fresh.
+pub const MAX_AGE: Duration = Duration::from_secs(120);
+
+/// A fetched page, like `gopher::fetch_url_bytes()` returns:
+///   (did tls work?, undecoded Gopher response)
+pub type Page = (bool, Vec<u8>);
+
+/// Least recently used pages are dropped first.
+pub struct Cache {
+    /// Most pages to keep.
+    size: usize,
+    /// Pages, their URLs, and when they were added, least recently
+    /// used first.
+    pages: Vec<(String, Instant, Page)>,
+}
+
+impl Default for Cache {
+    fn default() -> Cache {
+        Cache::new(DEFAULT_SIZE)
+    }
+}
+
+impl Cache {
+    /// Create a cache that holds `size` pages.
+    pub fn new(size: usize) -> Cache {
+        Cache {
+            size,
+            pages: vec![],
+        }
+    }
+
+    /// Get a page, making it the most recently used.
+    pub fn get(&mut self, url: &str) -> Option<&Page> {
+        self.expire();
+        let i = self.pages.iter().position(|(u, ..)| u == url)?;
+        let entry = self.pages.remove(i);
+        self.pages.push(entry);
+        self.pages.last().map(|(.., page)| page)
+    }
+
+    /// Remove a page and return it.
+    pub fn take(&mut self, url: &str) -> Option<Page> {
+        self.expire();
+        let i = self.pages.iter().position(|(u, ..)| u == url)?;
+        Some(self.pages.remove(i).2)
+    }
+
+    /// Is this URL's page in the cache?
+    pub fn contains(&self, url: &str) -> bool {
+        self.pages
+            .iter()
+            .any(|(u, added, _)| u == url && added.elapsed() < MAX_AGE)
+    }
+
+    /// Add or replace a page, dropping the least recently used one if
+    /// the cache is full.
+    pub fn insert(&mut self, url: String, page: Page) {
+        self.expire();
+        self.pages.retain(|(u, ..)| *u != url);
+        self.pages.push((url, Instant::now(), page));
+        if self.pages.len() > self.size {
+            self.pages.remove(0);
+        }
+    }
+
+    /// Drop pages that are too old to show.
+    fn expire(&mut self) {
+        self.pages.retain(|(_, added, _)| added.elapsed() < MAX_AGE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru() {
+        let page = |s: &str| (false, s.as_bytes().to_vec());
+        let mut cache = Cache::new(2);
+        cache.insert("gopher://a".into(), page("a"));
+        cache.insert("gopher://b".into(), page("b"));
+        assert_eq!(cache.get("gopher://a"), Some(&page("a")));
+        cache.insert("gopher://c".into(), page("c"));
+        assert!(cache.contains("gopher://a"));
+        assert!(!cache.contains("gopher://b"));
+        assert!(cache.contains("gopher://c"));
+
+        cache.insert("gopher://a".into(), page("new"));
+        assert_eq!(cache.get("gopher://a"), Some(&page("new")));
+        assert!(cache.get("gopher://b").is_none());
+
+        assert_eq!(cache.take("gopher://a"), Some(page("new")));
+        assert!(!cache.contains("gopher://a"));
+        assert!(cache.take("gopher://a").is_none());
+    }
+}
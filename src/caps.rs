@@ -0,0 +1,177 @@
+//! Many Gopher servers publish a `caps.txt` file describing
+//! themselves: the server software, how selectors are delimited, the
+//! default text encoding, and so on. See the spec at:
+//! gopher://gopher.floodgap.com/0/gopher/tech/caps.txt
+//!
+//! phetch fetches it lazily, the first time it's needed for a host,
+//! and the UI caches it for the rest of the session.
+
+use crate::gopher;
+
+/// Where servers publish their capabilities.
+pub const SELECTOR: &str = "/caps.txt";
+
+/// The parsed contents of a server's `caps.txt`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Caps {
+    /// KEY=VALUE fields, in the order they appeared.
+    fields: Vec<(String, String)>,
+}
+
+impl Caps {
+    /// Value for a key, if the server set it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All the fields, in order.
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// String separating the parts of a selector. The spec spells it
+    /// "PathDelimeter", but we accept both.
+    pub fn path_delimiter(&self) -> &str {
+        self.get("PathDelimeter")
+            .or_else(|| self.get("PathDelimiter"))
+            .filter(|d| !d.is_empty())
+            .unwrap_or("/")
+    }
+
+    /// Encoding the server uses for text that isn't UTF-8, if known.
+    pub fn charset(&self) -> Option<&str> {
+        self.get("ServerDefaultEncoding").filter(|c| !c.is_empty())
+    }
+}
+
+/// Parse a `caps.txt` file. Returns None if it doesn't start with
+/// the `CAPS` magic line, like when the server sends an error menu.
+pub fn parse(raw: &str) -> Option<Caps> {
+    let mut lines = raw
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    if lines.next()? != "CAPS" {
+        return None;
+    }
+
+    let mut caps = Caps::default();
+    for line in lines {
+        if line == "." {
+            break;
+        }
+        let mut parts = line.splitn(2, '=');
+        if let (Some(key), Some(val)) = (parts.next(), parts.next()) {
+            caps.fields
+                .push((key.trim().to_string(), val.trim().to_string()));
+        }
+    }
+    Some(caps)
+}
+
+/// Fetch and parse a server's `caps.txt`. Returns None if the server
+/// doesn't have one or it can't be reached.
+pub fn fetch(host: &str, port: &str, tls: bool, tor: bool) -> Option<Caps> {
+    gopher::fetch(host, port, SELECTOR, tls, tor)
+        .ok()
+        .and_then(|(_, raw)| parse(&raw))
+}
+
+/// Gophermap describing a server, for the server info page.
+pub fn as_raw_menu(host: &str, port: &str, caps: Option<&Caps>) -> String {
+    let mut out = format!("i{}:{}\r\ni\r\n", host, port);
+    out.push_str(&format!("1Server root\t/\t{}\t{}\r\n", host, port));
+
+    let caps = match caps {
+        Some(caps) => caps,
+        None => {
+            out.push_str("i\r\niThis server doesn't publish a caps.txt file.\r\n");
+            return out;
+        }
+    };
+    out.push_str(&format!(
+        "0caps.txt\t{}\t{}\t{}\r\ni\r\n",
+        SELECTOR, host, port
+    ));
+
+    let width = caps
+        .fields()
+        .iter()
+        .map(|(k, _)| k.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (key, val) in caps.fields() {
+        out.push_str(&format!(
+            "i{:width$}  {}\r\n",
+            key,
+            val.replace('\t', " "),
+            width = width
+        ));
+    }
+    if caps.fields().is_empty() {
+        out.push_str("iNo capabilities listed.\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPS: &str = "CAPS
+# This is an automatically generated caps file.
+
+CapsVersion=1
+ExpireCapsAfter=3600
+
+PathDelimeter=/
+PathIdentity=.
+PathParent=..
+ServerSoftware=Gophernicus
+ServerDefaultEncoding=ISO-8859-1
+ServerDescription=A server = with equals
+";
+
+    #[test]
+    fn test_parse() {
+        let caps = parse(CAPS).expect("should parse");
+        assert_eq!(caps.fields().len(), 8);
+        assert_eq!(caps.get("ServerSoftware"), Some("Gophernicus"));
+        assert_eq!(
+            caps.get("ServerDescription"),
+            Some("A server = with equals")
+        );
+        assert_eq!(caps.get("Nope"), None);
+        assert_eq!(caps.path_delimiter(), "/");
+        assert_eq!(caps.charset(), Some("ISO-8859-1"));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let caps = parse("CAPS\r\nPathDelimiter=:\r\n").expect("should parse");
+        assert_eq!(caps.path_delimiter(), ":");
+        assert_eq!(caps.charset(), None);
+        assert_eq!(Caps::default().path_delimiter(), "/");
+    }
+
+    #[test]
+    fn test_not_caps() {
+        assert_eq!(parse("3Not found\t\terror.host\t1\r\n.\r\n"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_as_raw_menu() {
+        let caps = parse("CAPS\nServerSoftware=phd\nPathDelimeter=/\n").unwrap();
+        assert_eq!(
+            as_raw_menu("sdf.org", "70", Some(&caps)),
+            "isdf.org:70\r\ni\r\n1Server root\t/\tsdf.org\t70\r\n\
+             0caps.txt\t/caps.txt\tsdf.org\t70\r\ni\r\n\
+             iServerSoftware  phd\r\niPathDelimeter   /\r\n"
+        );
+        assert!(as_raw_menu("sdf.org", "70", None).contains("doesn't publish"));
+    }
+}
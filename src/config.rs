@@ -3,7 +3,7 @@
 //!
 //! An example default config is provided but unused by this module.
 
-use crate::{phetchdir, ui};
+use crate::{batch, bookmarks, export, gopher::Type, history, mirror, phetchdir, server, ui};
 use std::{
     collections::HashMap,
     fs::OpenOptions,
@@ -34,6 +34,26 @@ wide no
 
 # Use emoji indicators for TLS & Tor. (--emoji)
 emoji no
+
+# Most pages to keep in history.gph. 0 for no limit.
+history_max 5000
+
+# Reopen the pages you had open last time. (--restore)
+restore no
+
+# On wide screens, preview the selected link next to menus.
+split no
+
+# Fetch links in the background before they're opened.
+prefetch no
+
+# Open downloads, web links (h), or telnet links with external
+# programs. %f is a temp file, %u is the URL, %h and %p are host
+# and port.
+# handler s mpv %f
+# handler I feh %f
+# handler h w3m %u
+# handler telnet ssh %h
 ";
 
 /// Not all the config options are available in the phetch.conf. We
@@ -51,8 +71,42 @@ pub struct Config {
     pub wide: bool,
     /// Render connection status as emoji
     pub emoji: bool,
+    /// External commands to open items with, keyed by Gopher type
+    /// char or "telnet".
+    pub handlers: HashMap<String, String>,
+    /// Most entries to keep in the history file. 0 for no limit.
+    pub history_max: usize,
+    /// Save open pages on quit and reopen them next time.
+    pub restore: bool,
+    /// Preview the selected link next to menus, if there's room.
+    pub split: bool,
+    /// Fetch links before they're opened.
+    pub prefetch: bool,
+    /// Was the start URL given on the command line? Can't be set in
+    /// conf file.
+    pub start_given: bool,
+    /// Don't save history. Can't be set in conf file.
+    pub private: bool,
     /// UI mode. Can't be set in conf file.
     pub mode: ui::Mode,
+    /// Port to listen on in --serve mode. Can't be set in conf file.
+    pub port: u16,
+    /// How many menus deep to go in --mirror mode. Can't be set in
+    /// conf file.
+    pub depth: usize,
+    /// Only mirror selectors under the starting one. Can't be set in
+    /// conf file.
+    pub prefix: bool,
+    /// How many URLs to fetch at once in --batch mode. Can't be set
+    /// in conf file.
+    pub jobs: usize,
+    /// Where to save output, ie the --mirror or --batch directory.
+    /// Can't be set in conf file.
+    pub output: Option<String>,
+    /// Output format for --print and --raw. Can't be set in conf file.
+    pub format: Option<export::Format>,
+    /// What `phetch bookmarks` should do. Can't be set in conf file.
+    pub bookmarks: Option<bookmarks::Command>,
 }
 
 impl Default for Config {
@@ -63,7 +117,21 @@ impl Default for Config {
             tor: false,
             wide: false,
             emoji: false,
+            handlers: HashMap::new(),
+            history_max: history::DEFAULT_MAX,
+            restore: false,
+            split: false,
+            prefetch: false,
+            start_given: false,
+            private: false,
             mode: ui::Mode::default(),
+            port: server::DEFAULT_PORT,
+            depth: mirror::DEFAULT_DEPTH,
+            prefix: false,
+            jobs: batch::DEFAULT_JOBS,
+            output: None,
+            format: None,
+            bookmarks: None,
         }
     }
 }
@@ -127,6 +195,19 @@ pub fn parse(text: &str) -> Result<Config> {
             "tls" => cfg.tls = to_bool(val)?,
             "tor" => cfg.tor = to_bool(val)?,
             "wide" => cfg.wide = to_bool(val)?,
+            "history_max" => cfg.history_max = to_number(val)?,
+            "restore" => cfg.restore = to_bool(val)?,
+            "split" => cfg.split = to_bool(val)?,
+            "prefetch" => cfg.prefetch = to_bool(val)?,
+            "handler" => {
+                let (typ, cmd) = parse_handler(val)
+                    .ok_or_else(|| error!("Wrong format for handler on line {}", linenum))?;
+                if cfg.handlers.contains_key(typ) {
+                    return Err(error!("Duplicate handler on line {}: {}", linenum, typ));
+                }
+                cfg.handlers.insert(typ.into(), cmd.into());
+                continue;
+            }
             _ => return Err(error!("Unknown key on line {}: {}", linenum, key)),
         }
         keys.insert(key, true);
@@ -135,6 +216,28 @@ pub fn parse(text: &str) -> Result<Config> {
     Ok(cfg)
 }
 
+/// Splits a handler's value like "s mpv %f" into the Gopher type
+/// char (or "telnet") and the command to run. Only types phetch
+/// hands off instead of showing can have a handler: downloads and
+/// web links.
+fn parse_handler(val: &str) -> Option<(&str, &str)> {
+    let mut parts = val.trim().splitn(2, ' ');
+    let typ = parts.next()?;
+    let cmd = parts.next()?.trim();
+    if cmd.is_empty() {
+        return None;
+    }
+    let mut chars = typ.chars();
+    match (chars.next(), chars.next()) {
+        _ if typ == "telnet" => Some((typ, cmd)),
+        (Some(c), None) => match Type::from(c) {
+            Some(t) if t.is_download() || t.is_html() => Some((typ, cmd)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Converts a config file's boolean value like "yes" or "false" to a
 /// real bool.
 fn to_bool(val: &str) -> Result<bool> {
@@ -145,6 +248,13 @@ fn to_bool(val: &str) -> Result<bool> {
     }
 }
 
+/// Converts a config file's numeric value like "5000" to a usize.
+fn to_number(val: &str) -> Result<usize> {
+    val.trim()
+        .parse()
+        .map_err(|_| error!("Not a number: {}", val))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,6 +267,17 @@ mod tests {
         assert_eq!(config.wide, false);
         assert_eq!(config.emoji, false);
         assert_eq!(config.start, "gopher://phetch/1/home");
+        assert_eq!(config.history_max, 5000);
+        assert!(!config.restore);
+        assert!(!config.split);
+        assert!(!config.prefetch);
+    }
+
+    #[test]
+    fn test_history_max() {
+        assert_eq!(parse("history_max 0").unwrap().history_max, 0);
+        let e = parse("history_max lots").unwrap_err();
+        assert_eq!(format!("{}", e), "Not a number: lots");
     }
 
     #[test]
@@ -199,4 +320,26 @@ mod tests {
         let e = res.unwrap_err();
         assert_eq!(format!("{}", e), "Duplicate key on line 4: tls");
     }
+
+    #[test]
+    fn test_handlers() {
+        let cfg = parse("handler s mpv %f\nhandler h w3m %u\nhandler telnet ssh %h").unwrap();
+        assert_eq!(cfg.handlers.len(), 3);
+        assert_eq!(cfg.handlers["s"], "mpv %f");
+        assert_eq!(cfg.handlers["h"], "w3m %u");
+        assert_eq!(cfg.handlers["telnet"], "ssh %h");
+
+        let e = parse("handler s mpv %f\nhandler s vlc %f").unwrap_err();
+        assert_eq!(format!("{}", e), "Duplicate handler on line 2: s");
+
+        let e = parse("handler mp3 mpv %f").unwrap_err();
+        assert_eq!(format!("{}", e), "Wrong format for handler on line 1");
+        assert!(parse("handler s").is_err());
+
+        // phetch shows these itself, so a handler would never run
+        for typ in &["0", "1", "7", "8", "i"] {
+            let e = parse(&format!("handler {} less %f", typ)).unwrap_err();
+            assert_eq!(format!("{}", e), "Wrong format for handler on line 1");
+        }
+    }
 }
@@ -0,0 +1,498 @@
+//! Export renders a page into a format other programs can use, for
+//! saving it to disk with the `e` key or printing it with `--format`.
+//! When saving, the format is picked by the file's extension: `.md`
+//! for Markdown, `.html` for a standalone web page, `.gph` for the raw
+//! Gophermap, `.json` and `.tsv` for scripts, and plain text for the
+//! rest.
+
+use crate::menu;
+use std::{
+    fs,
+    io::{Result, Write},
+    path::Path,
+};
+
+/// Formats a page can be exported to.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Format {
+    /// Rendered text, without colors.
+    Plain,
+    /// Markdown, with menu links as hyperlinks.
+    Markdown,
+    /// Standalone HTML page, with menu links as hyperlinks.
+    Html,
+    /// The raw Gopher response.
+    Gophermap,
+    /// JSON, with each menu line as an object.
+    Json,
+    /// Tab-separated values, with a header row.
+    Tsv,
+    /// Rendered text with numbered links, followed by a list of the
+    /// links' URLs. Like `lynx -dump`.
+    Dump,
+}
+
+impl Format {
+    /// Look up a format by name, ex: `--format json`
+    pub fn from_name(name: &str) -> Option<Format> {
+        Some(match name.to_lowercase().as_ref() {
+            "plain" | "text" | "txt" => Format::Plain,
+            "markdown" | "md" => Format::Markdown,
+            "html" => Format::Html,
+            "gophermap" | "gph" | "raw" => Format::Gophermap,
+            "json" => Format::Json,
+            "tsv" => Format::Tsv,
+            "dump" => Format::Dump,
+            _ => return None,
+        })
+    }
+
+    /// Pick a format based on a file's extension.
+    pub fn for_path(path: &Path) -> Format {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_ref() {
+            "md" | "markdown" => Format::Markdown,
+            "html" | "htm" => Format::Html,
+            "gph" | "gophermap" => Format::Gophermap,
+            "json" => Format::Json,
+            "tsv" => Format::Tsv,
+            _ if name == "gophermap" => Format::Gophermap,
+            _ => Format::Plain,
+        }
+    }
+}
+
+/// Render a page in the given format. `menu` says whether `raw` is
+/// a Gophermap or a text document.
+pub fn render(url: &str, raw: &str, menu: bool, format: Format) -> String {
+    match format {
+        Format::Plain => plain(url, raw, menu),
+        Format::Markdown => markdown(url, raw, menu),
+        Format::Html => html(url, raw, menu),
+        Format::Gophermap => raw.to_string(),
+        Format::Json => json(url, raw, menu),
+        Format::Tsv => tsv(url, raw, menu),
+        Format::Dump => dump(url, raw, menu),
+    }
+}
+
+/// Render a page and write it to `path`, in the format its extension
+/// calls for. Returns the format used. Unless `overwrite` is set, it's
+/// an `AlreadyExists` error if there's a file there already.
+pub fn save(path: &Path, url: &str, raw: &str, menu: bool, overwrite: bool) -> Result<Format> {
+    let format = Format::for_path(path);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(!overwrite)
+        .create(overwrite)
+        .truncate(overwrite)
+        .open(path)?;
+    file.write_all(render(url, raw, menu, format).as_bytes())?;
+    Ok(format)
+}
+
+/// Plain text, the way `phetch --print` shows it when piped.
+pub fn plain(url: &str, raw: &str, menu: bool) -> String {
+    if !menu {
+        return text_body(raw).to_string();
+    }
+    let menu = menu::parse(url, raw.to_string());
+    let mut out = String::new();
+    for line in &menu.lines {
+        out.push_str(line.text(&menu.raw));
+        out.push('\n');
+    }
+    out
+}
+
+/// Markdown. Runs of info lines become code blocks, to keep their
+/// ASCII art and layout intact, and links become list items.
+pub fn markdown(url: &str, raw: &str, menu: bool) -> String {
+    if !menu {
+        let body = text_body(raw);
+        let fence = fence_for(body);
+        return format!("<{}>\n\n{}\n{}\n{}\n", url, fence, body.trim_end(), fence);
+    }
+
+    let menu = menu::parse(url, raw.to_string());
+    let mut out = String::new();
+    let mut info = vec![];
+    for line in &menu.lines {
+        if line.typ.is_link() {
+            push_code_block(&mut out, &mut info);
+            out.push_str(&format!(
+                "- [{}](<{}>)\n",
+                escape_markdown(line.text(&menu.raw)),
+                line.url(&menu.raw)
+            ));
+        } else {
+            info.push(line.text(&menu.raw));
+        }
+    }
+    push_code_block(&mut out, &mut info);
+    out
+}
+
+/// Standalone HTML page.
+pub fn html(url: &str, raw: &str, menu: bool) -> String {
+    let mut body = String::new();
+    if menu {
+        let menu = menu::parse(url, raw.to_string());
+        for line in &menu.lines {
+            let text = escape_html(line.text(&menu.raw));
+            if line.typ.is_link() {
+                body.push_str(&format!(
+                    "<a href=\"{}\">{}</a>\n",
+                    escape_html(&line.url(&menu.raw)),
+                    text
+                ));
+            } else {
+                body.push_str(&text);
+                body.push('\n');
+            }
+        }
+    } else {
+        body.push_str(&escape_html(text_body(raw)));
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{url}</title>
+</head>
+<body>
+<pre>
+{body}</pre>
+</body>
+</html>
+",
+        url = escape_html(url),
+        body = body
+    )
+}
+
+/// Rendered text for email or archiving. Menus are laid out like
+/// they are in phetch, with numbered links, and end with a list of
+/// References mapping each number to its URL.
+pub fn dump(url: &str, raw: &str, menu: bool) -> String {
+    if !menu {
+        return text_body(raw).to_string();
+    }
+
+    let menu = menu::parse(url, raw.to_string());
+    let mut out = String::new();
+    let mut refs = String::new();
+    for line in &menu.lines {
+        let text = line.text(&menu.raw);
+        if line.typ.is_link() {
+            let num = line.link + 1;
+            out.push_str(&format!("  {:>2}. {}\n", num, text));
+            refs.push_str(&format!("  {:>2}. {}\n", num, line.url(&menu.raw)));
+        } else {
+            out.push_str(&format!("      {}\n", text));
+        }
+    }
+    if !refs.is_empty() {
+        out.push_str("\nReferences\n\n");
+        out.push_str(&refs);
+    }
+    out
+}
+
+/// JSON. Menus are an array of lines, text documents an object with
+/// the URL and body.
+pub fn json(url: &str, raw: &str, menu: bool) -> String {
+    if !menu {
+        return format!(
+            "{{\"url\": {}, \"body\": {}}}\n",
+            json_string(url),
+            json_string(text_body(raw))
+        );
+    }
+
+    let menu = menu::parse(url, raw.to_string());
+    let mut out = String::from("[");
+    for (i, line) in menu.lines.iter().enumerate() {
+        let (sel, host, port) = line.fields(&menu.raw);
+        out.push_str(if i == 0 { "\n  " } else { ",\n  " });
+        out.push_str(&format!(
+            "{{\"type\": {}, \"text\": {}, \"selector\": {}, \"host\": {}, \"port\": {}, \"url\": {}}}",
+            json_string(&line.typ.to_string()),
+            json_string(line.text(&menu.raw)),
+            json_string(sel),
+            json_string(host),
+            json_string(port),
+            json_string(&line.url(&menu.raw)),
+        ));
+    }
+    out.push_str(if menu.lines.is_empty() {
+        "]\n"
+    } else {
+        "\n]\n"
+    });
+    out
+}
+
+/// Tab-separated values. Menus get a row per line, text documents a
+/// single row with the URL and body.
+pub fn tsv(url: &str, raw: &str, menu: bool) -> String {
+    if !menu {
+        return format!(
+            "url\tbody\n{}\t{}\n",
+            tsv_field(url),
+            tsv_field(text_body(raw))
+        );
+    }
+
+    let menu = menu::parse(url, raw.to_string());
+    let mut out = String::from("type\ttext\tselector\thost\tport\turl\n");
+    for line in &menu.lines {
+        let (sel, host, port) = line.fields(&menu.raw);
+        let fields = [
+            line.typ.to_string(),
+            line.text(&menu.raw).to_string(),
+            sel.to_string(),
+            host.to_string(),
+            port.to_string(),
+            line.url(&menu.raw),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| tsv_field(f)).collect();
+        out.push_str(&fields.join("\t"));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quote and escape a string for JSON.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Escape tabs, newlines, and backslashes in a TSV field.
+fn tsv_field(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Text document without Gopher's "end of response" line.
+fn text_body(raw: &str) -> &str {
+    for end in &["\n.\r\n", "\n.\n"] {
+        if raw.ends_with(end) {
+            return &raw[..raw.len() - end.len() + 1];
+        }
+    }
+    raw
+}
+
+/// Flush a run of info lines to a Markdown code block.
+fn push_code_block(out: &mut String, info: &mut Vec<&str>) {
+    if info.is_empty() {
+        return;
+    }
+    let block = info.join("\n");
+    let fence = fence_for(&block);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(&format!("{}\n{}\n{}\n\n", fence, block, fence));
+    info.clear();
+}
+
+/// Markdown code fence longer than any run of backticks in `text`.
+fn fence_for(text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        if c == '`' {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    "`".repeat(3.max(longest + 1))
+}
+
+/// Escape text for a Markdown link label.
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape text for HTML.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MENU: &str = "i  ~ phlog ~\t\terror.host\t1\r
+i\t\terror.host\t1\r
+1Posts\t/posts\tsdf.org\t70\r
+0Notes <2020>\t/notes.txt\tsdf.org\t7070\r
+hWeb\tURL:https://example.com?a=1&b=2\t\t70\r
+.\r
+";
+
+    #[test]
+    fn test_for_path() {
+        assert_eq!(Format::for_path(Path::new("page.md")), Format::Markdown);
+        assert_eq!(Format::for_path(Path::new("page.HTML")), Format::Html);
+        assert_eq!(Format::for_path(Path::new("page.gph")), Format::Gophermap);
+        assert_eq!(
+            Format::for_path(Path::new("/tmp/gophermap")),
+            Format::Gophermap
+        );
+        assert_eq!(Format::for_path(Path::new("page.txt")), Format::Plain);
+        assert_eq!(Format::for_path(Path::new("page")), Format::Plain);
+    }
+
+    #[test]
+    fn test_plain() {
+        assert_eq!(
+            plain("test", MENU, true),
+            "  ~ phlog ~\n\nPosts\nNotes <2020>\nWeb\n"
+        );
+        assert_eq!(plain("test", "Hi there.\r\n.\r\n", false), "Hi there.\r\n");
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            markdown("test", MENU, true),
+            "```
+  ~ phlog ~
+
+```
+
+- [Posts](<gopher://sdf.org/1/posts>)
+- [Notes \\<2020\\>](<gopher://sdf.org:7070/0/notes.txt>)
+- [Web](<https://example.com?a=1&b=2>)
+"
+        );
+        assert_eq!(
+            markdown("gopher://sdf.org/0/x", "Use ```code```.\r\n.\r\n", false),
+            "<gopher://sdf.org/0/x>\n\n````\nUse ```code```.\n````\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let out = html("gopher://sdf.org", MENU, true);
+        assert!(out.starts_with("<!DOCTYPE html>"));
+        assert!(out.contains("<title>gopher://sdf.org</title>"));
+        assert!(
+            out.contains("<pre>\n  ~ phlog ~\n\n<a href=\"gopher://sdf.org/1/posts\">Posts</a>\n")
+        );
+        assert!(
+            out.contains("<a href=\"gopher://sdf.org:7070/0/notes.txt\">Notes &lt;2020&gt;</a>\n")
+        );
+        assert!(out.contains("<a href=\"https://example.com?a=1&amp;b=2\">Web</a>\n</pre>"));
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            json("test", MENU, true),
+            r#"[
+  {"type": "i", "text": "  ~ phlog ~", "selector": "", "host": "error.host", "port": "1", "url": ""},
+  {"type": "i", "text": "", "selector": "", "host": "error.host", "port": "1", "url": ""},
+  {"type": "1", "text": "Posts", "selector": "/posts", "host": "sdf.org", "port": "70", "url": "gopher://sdf.org/1/posts"},
+  {"type": "0", "text": "Notes <2020>", "selector": "/notes.txt", "host": "sdf.org", "port": "7070", "url": "gopher://sdf.org:7070/0/notes.txt"},
+  {"type": "h", "text": "Web", "selector": "URL:https://example.com?a=1&b=2", "host": "", "port": "70", "url": "https://example.com?a=1&b=2"}
+]
+"#
+        );
+        assert_eq!(json("test", "", true), "[]\n");
+        assert_eq!(
+            json("gopher://sdf.org/0/x", "Say \"hi\"\\\t\x01\r\n.\r\n", false),
+            "{\"url\": \"gopher://sdf.org/0/x\", \"body\": \"Say \\\"hi\\\"\\\\\\t\\u0001\\r\\n\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv() {
+        let out = tsv("test", MENU, true);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "type\ttext\tselector\thost\tport\turl");
+        assert_eq!(
+            lines[3],
+            "1\tPosts\t/posts\tsdf.org\t70\tgopher://sdf.org/1/posts"
+        );
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            tsv("gopher://sdf.org/0/x", "a\tb\\c\nd\n.\n", false),
+            "url\tbody\ngopher://sdf.org/0/x\ta\\tb\\\\c\\nd\\n\n"
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(
+            dump("test", MENU, true),
+            "        ~ phlog ~
+      
+   1. Posts
+   2. Notes <2020>
+   3. Web
+
+References
+
+   1. gopher://sdf.org/1/posts
+   2. gopher://sdf.org:7070/0/notes.txt
+   3. https://example.com?a=1&b=2
+"
+        );
+        assert_eq!(dump("test", "iJust info\r\n", true), "      Just info\n");
+        assert_eq!(dump("test", "Hi.\r\n.\r\n", false), "Hi.\r\n");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Format::from_name("JSON"), Some(Format::Json));
+        assert_eq!(Format::from_name("markdown"), Some(Format::Markdown));
+        assert_eq!(Format::from_name("plain"), Some(Format::Plain));
+        assert_eq!(Format::from_name("xml"), None);
+    }
+
+    #[test]
+    fn test_gophermap() {
+        assert_eq!(render("test", MENU, true, Format::Gophermap), MENU);
+    }
+}
@@ -0,0 +1,228 @@
+//! The finder searches everything in your history and bookmarks from
+//! a single prompt. Queries are fuzzy: each word just needs its
+//! letters to appear, in order, in a page's title or URL. Matches
+//! are ranked by how well they match and by frecency, a mix of how
+//! often and how recently you've visited a page.
+
+use crate::{bookmarks, history};
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Frecency points for being bookmarked, about as many as a visit
+/// in the last few days.
+const BOOKMARK_POINTS: u64 = 140;
+
+/// A page that can be found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// Bookmark label or page title.
+    pub label: String,
+    /// Gopher URL.
+    pub url: String,
+    /// Higher for pages visited often and recently.
+    pub frecency: u64,
+    /// Is it bookmarked?
+    pub bookmarked: bool,
+}
+
+/// Everything in your history and bookmarks, one Item per URL.
+pub fn load() -> Vec<Item> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let history = history::load().unwrap_or_default();
+    let bookmarks = bookmarks::load_all()
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|marks| marks.list())
+        .collect::<Vec<_>>();
+    items(&history, &bookmarks, now)
+}
+
+/// Combine history entries and bookmarks into Items, most frecent
+/// first. Bookmark labels win over page titles.
+fn items(history: &[history::Entry], bookmarks: &[bookmarks::Bookmark], now: u64) -> Vec<Item> {
+    let mut items: Vec<Item> = vec![];
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for mark in bookmarks {
+        if seen.contains_key(&mark.url) {
+            continue;
+        }
+        seen.insert(mark.url.clone(), items.len());
+        items.push(Item {
+            label: mark.label.clone(),
+            url: mark.url.clone(),
+            frecency: BOOKMARK_POINTS,
+            bookmarked: true,
+        });
+    }
+    for entry in history.iter().rev() {
+        let points = entry.visits as u64 * recency(entry.time, now);
+        if let Some(&i) = seen.get(&entry.url) {
+            items[i].frecency += points;
+        } else {
+            seen.insert(entry.url.clone(), items.len());
+            items.push(Item {
+                label: entry.label.clone(),
+                url: entry.url.clone(),
+                frecency: points,
+                bookmarked: false,
+            });
+        }
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(item.frecency));
+    items
+}
+
+/// Points for a single visit, depending on how long ago it was.
+fn recency(time: u64, now: u64) -> u64 {
+    let days = now.saturating_sub(time) / (24 * 60 * 60);
+    match days {
+        _ if time == 0 => 10,
+        0..=3 => 100,
+        4..=14 => 70,
+        15..=31 => 50,
+        32..=90 => 30,
+        _ => 10,
+    }
+}
+
+/// Items matching a query, best first. Everything matches an empty
+/// query, in frecency order.
+pub fn search<'a>(items: &'a [Item], query: &str) -> Vec<&'a Item> {
+    let mut found: Vec<(f64, &Item)> = items
+        .iter()
+        .filter_map(|item| {
+            let score = fuzzy(query, &item.label).max(fuzzy(query, &item.url))?;
+            let boost = 1.0 + (item.frecency as f64).ln_1p() / 10.0;
+            Some((score as f64 * boost, item))
+        })
+        .collect();
+    // stable, so ties stay in frecency order
+    found.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    found.into_iter().map(|(_, item)| item).collect()
+}
+
+/// How well `text` matches `query`, or None if it doesn't. Each word
+/// in the query has to appear in `text`, ignoring case, either whole
+/// or with its letters spread out in order. Whole words score higher,
+/// as do letters in a row and letters starting a word.
+fn fuzzy(query: &str, text: &str) -> Option<usize> {
+    let lower = text.to_lowercase();
+    let text: Vec<char> = lower.chars().collect();
+    let starts_word = |i: usize| i == 0 || !text[i - 1].is_alphanumeric();
+    let mut score = 0;
+    for word in query.to_lowercase().split_whitespace() {
+        if let Some(byte) = lower.find(word) {
+            let i = lower[..byte].chars().count();
+            score += 6 * word.chars().count() + if starts_word(i) { 3 } else { 0 };
+            continue;
+        }
+        let mut pos = 0;
+        let mut last = None;
+        for c in word.chars() {
+            let i = pos + text[pos..].iter().position(|&t| t == c)?;
+            score += 1;
+            if last.map(|l| l + 1) == Some(i) {
+                score += 5;
+            }
+            if starts_word(i) {
+                score += 3;
+            }
+            last = Some(i);
+            pos = i + 1;
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 1000 * DAY;
+
+    fn entry(label: &str, url: &str, time: u64, visits: usize) -> history::Entry {
+        history::Entry {
+            label: label.into(),
+            url: url.into(),
+            time,
+            visits,
+        }
+    }
+
+    fn urls(items: &[&Item]) -> Vec<String> {
+        items.iter().map(|i| i.url.clone()).collect()
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        assert!(fuzzy("sdf", "gopher://sdf.org").is_some());
+        assert!(fuzzy("SDF phlog", "SDF Phlogosphere").is_some());
+        assert!(fuzzy("fds", "gopher://sdf.org").is_none());
+        assert!(fuzzy("sdf lawn", "SDF Phlogosphere").is_none());
+        assert_eq!(fuzzy("", "anything"), Some(0));
+        // in a row and at the start of a word beats scattered
+        assert!(fuzzy("law", "The Lawn") > fuzzy("law", "Look at web"));
+        assert!(fuzzy("lwn", "The Lawn") > fuzzy("lwn", "Lower town"));
+    }
+
+    #[test]
+    fn test_items() {
+        let history = vec![
+            entry("Old", "gopher://old.org", NOW - 200 * DAY, 3),
+            entry("SDF", "gopher://sdf.org", NOW - 2 * DAY, 1),
+            entry("Lawn", "gopher://bitreich.org/1/lawn", NOW - DAY, 2),
+            entry("SDF!", "gopher://sdf.org", NOW, 1),
+        ];
+        let marks = vec![bookmarks::Bookmark {
+            label: "My SDF".into(),
+            url: "gopher://sdf.org".into(),
+        }];
+        let items = items(&history, &marks, NOW);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].label, "My SDF");
+        assert!(items[0].bookmarked);
+        assert_eq!(items[0].frecency, BOOKMARK_POINTS + 200);
+        assert_eq!(items[1].frecency, 200);
+        assert_eq!(items[2].frecency, 30);
+    }
+
+    #[test]
+    fn test_search() {
+        let history = vec![
+            entry("The Lawn", "gopher://bitreich.org/1/lawn", NOW, 1),
+            entry("Weather", "gopher://weather.org/1/lookup", NOW, 5),
+            entry("SDF", "gopher://sdf.org", NOW, 1),
+        ];
+        let items = items(&history, &[], NOW);
+        assert_eq!(
+            urls(&search(&items, "")),
+            vec![
+                "gopher://weather.org/1/lookup",
+                "gopher://sdf.org",
+                "gopher://bitreich.org/1/lawn"
+            ]
+        );
+        assert_eq!(
+            urls(&search(&items, "lawn")),
+            vec!["gopher://bitreich.org/1/lawn"]
+        );
+        assert_eq!(
+            urls(&search(&items, "org l")),
+            vec![
+                "gopher://weather.org/1/lookup",
+                "gopher://bitreich.org/1/lawn"
+            ]
+        );
+        assert_eq!(
+            urls(&search(&items, "the"))[0],
+            "gopher://bitreich.org/1/lawn"
+        );
+        assert!(search(&items, "zzz").is_empty());
+    }
+}
@@ -5,12 +5,14 @@
 //! IPv6 addresses.
 
 use std::{
-    fs,
-    io::{Read, Result, Write},
+    fmt, fs,
+    io::{ErrorKind, Read, Result, Write},
     net::TcpStream,
     net::ToSocketAddrs,
     os::unix::fs::OpenOptionsExt,
-    time::Duration,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use termion::input::TermRead;
 
@@ -88,12 +90,52 @@ pub fn fetch(
     tls: bool,
     tor: bool,
 ) -> Result<(bool, String)> {
+    let (tls, body) = fetch_bytes(host, port, selector, tls, tor)?;
+    Ok((tls, decode(&body, None)))
+}
+
+/// Fetches a gopher URL and returns a tuple of:
+///   (did tls work?, undecoded Gopher response)
+pub fn fetch_url_bytes(url: &str, tls: bool, tor: bool) -> Result<(bool, Vec<u8>)> {
+    let u = parse_url(url);
+    fetch_bytes(u.host, u.port, u.sel, tls, tor)
+}
+
+/// Fetches a gopher URL by its component parts and returns a tuple of:
+///   (did tls work?, undecoded Gopher response)
+pub fn fetch_bytes(
+    host: &str,
+    port: &str,
+    selector: &str,
+    tls: bool,
+    tor: bool,
+) -> Result<(bool, Vec<u8>)> {
     let mut stream = request(host, port, selector, tls, tor)?;
     let mut body = Vec::new();
     stream.read_to_end(&mut body)?;
-    let mut out = String::from_utf8_lossy(&body).to_string();
+    Ok((stream.is_tls(), body))
+}
+
+/// Turns a Gopher response into a printable String. Responses that
+/// aren't valid UTF-8 are decoded using `charset`, if it's one we
+/// know, otherwise invalid bytes are replaced.
+pub fn decode(body: &[u8], charset: Option<&str>) -> String {
+    let mut out = match std::str::from_utf8(body) {
+        Ok(s) => s.to_string(),
+        Err(_) if charset.is_some_and(is_latin1) => body.iter().map(|&b| b as char).collect(),
+        Err(_) => String::from_utf8_lossy(body).to_string(),
+    };
     clean_response(&mut out);
-    Ok((stream.is_tls(), out))
+    out
+}
+
+/// Is this the name of the ISO-8859-1 charset? Its bytes map
+/// directly to the first 256 Unicode code points.
+fn is_latin1(charset: &str) -> bool {
+    matches!(
+        charset.to_lowercase().as_ref(),
+        "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "latin-1" | "l1"
+    )
 }
 
 /// Removes unprintable characters from Gopher response.
@@ -111,24 +153,103 @@ fn clean_response(res: &mut String) {
 ///   (path it was saved to, the size in bytes)
 pub fn download_url(url: &str, tls: bool, tor: bool) -> Result<(String, usize)> {
     let u = parse_url(url);
-    let filename = u
-        .sel
-        .split_terminator('/')
-        .rev()
-        .nth(0)
-        .ok_or_else(|| error!("Bad download filename: {}", u.sel))?;
-    let mut path = std::path::PathBuf::from(".");
-    path.push(filename);
-    let stdin = termion::async_stdin();
-    let mut keys = stdin.keys();
+    let filename = download_filename(url)?;
+    let mut path = PathBuf::from(".");
+    path.push(&filename);
 
     let mut stream = request(u.host, u.port, u.sel, tls, tor)?;
-    let mut file = fs::OpenOptions::new()
+    let file = fs::OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
         .mode(0o770)
         .open(&path)?;
+    let bytes = save_stream(&mut stream, file, &path)?;
+    Ok((filename, bytes))
+}
+
+/// The filename a download should be saved as, based on its selector.
+pub fn download_filename(url: &str) -> Result<String> {
+    let u = parse_url(url);
+    u.sel
+        .split_terminator('/')
+        .rev()
+        .nth(0)
+        .map(|s| s.to_string())
+        .ok_or_else(|| error!("Bad download filename: {}", u.sel))
+}
+
+/// Streams the raw bytes of a Gopher URL to `out`, calling
+/// `progress` with the running total after each chunk. Returns the
+/// size in bytes.
+pub fn copy_url(
+    url: &str,
+    out: &mut dyn Write,
+    tls: bool,
+    tor: bool,
+    progress: &mut dyn FnMut(usize),
+) -> Result<usize> {
+    let u = parse_url(url);
+    let mut stream = request(u.host, u.port, u.sel, tls, tor)?;
+    let mut buf = [0; 8192];
+    let mut bytes = 0;
+    loop {
+        let count = stream.read(&mut buf)?;
+        if count == 0 {
+            break;
+        }
+        out.write_all(&buf[..count])?;
+        bytes += count;
+        progress(bytes);
+    }
+    out.flush()?;
+    Ok(bytes)
+}
+
+/// Downloads a binary to a new temp file that only we can read, for
+/// handing to a handler command. Allows canceling with Ctrl-c.
+/// Returns the path, which the caller should remove when it's done.
+pub fn download_url_to_temp(url: &str, tls: bool, tor: bool) -> Result<PathBuf> {
+    let u = parse_url(url);
+    let filename = download_filename(url)?;
+    let mut stream = request(u.host, u.port, u.sel, tls, tor)?;
+
+    // a name nobody could have guessed and created first, but if
+    // someone did, create_new() won't follow or reuse their file
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let mut tries = 0;
+    let (path, file) = loop {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "phetch-{}-{:x}-{}",
+            process::id(),
+            nanos.wrapping_add(tries),
+            filename
+        ));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => break (path, file),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && tries < 10 => tries += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    save_stream(&mut stream, file, &path)?;
+    Ok(path)
+}
+
+/// Writes a response to a file that's been opened for it. Allows
+/// canceling with Ctrl-c, which removes the file. Returns the size in
+/// bytes.
+fn save_stream(stream: &mut Stream, mut file: fs::File, path: &Path) -> Result<usize> {
+    let stdin = termion::async_stdin();
+    let mut keys = stdin.keys();
 
     let mut buf = [0; 1024];
     let mut bytes = 0;
@@ -137,7 +258,10 @@ pub fn download_url(url: &str, tls: bool, tor: bool) -> Result<(String, usize)>
             break;
         }
         bytes += count;
-        file.write_all(&buf[..count])?;
+        if let Err(e) = file.write_all(&buf[..count]) {
+            fs::remove_file(path).ok();
+            return Err(e);
+        }
         if let Some(Ok(termion::event::Key::Ctrl('c'))) = keys.next() {
             if path.exists() {
                 fs::remove_file(path)?;
@@ -145,7 +269,7 @@ pub fn download_url(url: &str, tls: bool, tor: bool) -> Result<(String, usize)>
             return Err(error!("Download cancelled"));
         }
     }
-    Ok((filename.to_string(), bytes))
+    Ok(bytes)
 }
 
 /// Make a Gopher request and return a TcpStream ready to be read()'d.
@@ -227,6 +351,64 @@ impl<'a> Url<'a> {
     }
 }
 
+impl<'a> fmt::Display for Url<'a> {
+    /// Formats a Url as a string that `parse_url()` understands. IPv6
+    /// hosts are wrapped in brackets and the default port is left off.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.typ.is_html() && self.host.is_empty() {
+            return write!(f, "{}", self.sel);
+        }
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.to_string()
+        };
+        if self.typ.is_telnet() {
+            return write!(f, "telnet://{}:{}", host, self.port);
+        }
+        write!(f, "gopher://{}", host)?;
+        if self.port != "70" {
+            write!(f, ":{}", self.port)?;
+        }
+        if self.sel.is_empty() || self.sel == "/" {
+            Ok(())
+        } else {
+            write!(f, "/{}{}", self.typ, self.sel)
+        }
+    }
+}
+
+/// URL of the menu containing a Gopher URL, ie its selector with the
+/// last part removed. `delimiter` separates the parts of a selector,
+/// which is "/" unless the server's caps.txt says otherwise. Returns
+/// None if the URL is already at the server root.
+pub fn parent_url(url: &str, delimiter: &str) -> Option<String> {
+    let u = parse_url(url);
+    if u.host.is_empty() || u.typ.is_telnet() || u.typ == Type::Error {
+        return None;
+    }
+    // searches live in the menu of the search item itself
+    let sel = u.sel.split('?').next().unwrap_or("");
+    let sel = sel.trim_end_matches(delimiter);
+    if sel.is_empty() && u.sel.trim_end_matches(delimiter).is_empty() {
+        return None;
+    }
+    let parent = match sel.rfind(delimiter) {
+        Some(idx) => &sel[..idx + delimiter.len()],
+        None => "",
+    };
+    Some(Url::new(Type::Menu, u.host, u.port, parent).to_string())
+}
+
+/// URL of the root menu of a Gopher URL's server.
+pub fn root_url(url: &str) -> Option<String> {
+    let u = parse_url(url);
+    if u.host.is_empty() || u.typ.is_telnet() || u.typ == Type::Error {
+        return None;
+    }
+    Some(Url::new(Type::Menu, u.host, u.port, "").to_string())
+}
+
 /// Given a Gopher URL, returns a gopher::Type.
 pub fn type_for_url(url: &str) -> Type {
     if url.starts_with("telnet://") {
@@ -280,12 +462,11 @@ pub fn parse_url(url: &str) -> Url {
     // ipv6
     if let Some(idx) = host.find('[') {
         if let Some(end) = host[idx + 1..].find(']') {
-            host = &host[idx + 1..=end];
-            if host.len() > end {
-                if let Some(idx) = host[end..].find(':') {
-                    port = &host[idx + 1..];
-                }
+            let end = idx + 1 + end;
+            if host[end + 1..].starts_with(':') && host.len() > end + 2 {
+                port = &host[end + 2..];
             }
+            host = &host[idx + 1..end];
         } else {
             return Url::new(Type::Error, "Unclosed ipv6 bracket", "", url);
         }
@@ -375,7 +556,7 @@ mod tests {
         let url = parse_url(urls[6]);
         assert_eq!(url.typ, Type::Menu);
         assert_eq!(url.host, "1234:2345:dead:4567:7890:1234:beef:1111");
-        assert_eq!(url.port, "70");
+        assert_eq!(url.port, "7443");
         assert_eq!(url.sel, "/files");
 
         let url = parse_url(urls[7]);
@@ -444,6 +625,105 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_url_to_string() {
+        let urls = vec![
+            "gopher://gopher.club/1/phlogs/",
+            "gopher://sdf.org:7777/1/maps",
+            "gopher://gopher.floodgap.org",
+            "gopher://gopher.floodgap.com/0/gopher/relevance.txt",
+            "gopher://[1234:2345:dead:4567:7890:1234:beef:1111]:7443/1/files",
+            "gopher://[::1]",
+            "https://github.com/xvxx/phetch",
+            "telnet://bbs.impakt.net:6502",
+        ];
+        for url in urls {
+            assert_eq!(parse_url(url).to_string(), url);
+        }
+        assert_eq!(parse_url("sdf.org/1/").to_string(), "gopher://sdf.org");
+    }
+
+    #[test]
+    fn test_parent_url() {
+        let parent = |url| parent_url(url, "/");
+        assert_eq!(parent("sdf.org"), None);
+        assert_eq!(parent("gopher://sdf.org/1/"), None);
+        assert_eq!(parent("gopher://sdf.org/1//"), None);
+        assert_eq!(
+            parent("gopher://sdf.org/1/maps"),
+            Some("gopher://sdf.org".into())
+        );
+        assert_eq!(
+            parent("gopher://sdf.org/1/maps/"),
+            Some("gopher://sdf.org".into())
+        );
+        assert_eq!(
+            parent("gopher://sdf.org/0/users/frog/notes.txt"),
+            Some("gopher://sdf.org/1/users/frog/".into())
+        );
+        assert_eq!(
+            parent("gopher://sdf.org/1/users/frog//"),
+            Some("gopher://sdf.org/1/users/".into())
+        );
+        assert_eq!(
+            parent("gopher://sdf.org/1/1/"),
+            Some("gopher://sdf.org".into())
+        );
+        assert_eq!(
+            parent("gopher://sdf.org:7070/1/phlog/2020/"),
+            Some("gopher://sdf.org:7070/1/phlog/".into())
+        );
+        assert_eq!(
+            parent("gopher://[::1]:7070/0/docs/readme.txt"),
+            Some("gopher://[::1]:7070/1/docs/".into())
+        );
+        assert_eq!(
+            parent("gopher://[2001:cdba::3257:9652]/1/files"),
+            Some("gopher://[2001:cdba::3257:9652]".into())
+        );
+        assert_eq!(
+            parent("gopher://gopherpedia.com/7/lookup?Gopher"),
+            Some("gopher://gopherpedia.com".into())
+        );
+        assert_eq!(
+            parent("gopher://sdf.org/1/relative/path"),
+            Some("gopher://sdf.org/1/relative/".into())
+        );
+        assert_eq!(
+            parent_url("gopher://sdf.org/0/docs:guide:intro", ":"),
+            Some("gopher://sdf.org/1/docs:guide:".into())
+        );
+        assert_eq!(parent("https://github.com/xvxx/phetch"), None);
+        assert_eq!(parent("telnet://bbs.impakt.net:6502"), None);
+    }
+
+    #[test]
+    fn test_root_url() {
+        assert_eq!(
+            root_url("gopher://sdf.org/0/users/frog/notes.txt"),
+            Some("gopher://sdf.org".into())
+        );
+        assert_eq!(
+            root_url("gopher://sdf.org:7070/1/phlog/"),
+            Some("gopher://sdf.org:7070".into())
+        );
+        assert_eq!(
+            root_url("gopher://[::1]:7070/1/files"),
+            Some("gopher://[::1]:7070".into())
+        );
+        assert_eq!(root_url("https://github.com/xvxx/phetch"), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"caf\xc3\xa9", None), "café");
+        assert_eq!(decode(b"caf\xe9", Some("ISO-8859-1")), "café");
+        assert_eq!(decode(b"caf\xe9", None), "caf\u{FFFD}");
+        assert_eq!(decode(b"caf\xe9", Some("KOI8-R")), "caf\u{FFFD}");
+        assert_eq!(decode(b"caf\xc3\xa9", Some("latin1")), "café");
+        assert_eq!(decode(b"a\x7fb", None), "ab");
+    }
+
     #[test]
     fn test_clean_response() {
         let mut test = "Hi".to_string();
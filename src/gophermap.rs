@@ -0,0 +1,348 @@
+//! Helpers for turning local files into Gopher menus: generating a
+//! menu for a directory, and resolving the relative selectors in a
+//! hand-written `gophermap` against the directory it lives in.
+//!
+//! Both take a `Linker`, which decides where links to local files
+//! should point. When serving a directory, that's a selector on our
+//! server. When browsing `file://` URLs, it's another `file://` URL.
+
+use crate::{gopher::Type, menu};
+use std::{
+    fs,
+    io::Result,
+    path::{Component, Path, PathBuf},
+};
+
+/// Name of the file that overrides a directory's generated menu.
+pub const GOPHERMAP: &str = "gophermap";
+
+/// Turns a local path into the (selector, host, port) fields of a
+/// Gophermap line.
+pub type Linker<'a> = &'a dyn Fn(&Path) -> (String, String, String);
+
+/// Is this file a Gophermap, either by name or `.gph` extension?
+pub fn is_gophermap(path: &Path) -> bool {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name == GOPHERMAP || name.ends_with(".gph"),
+        None => false,
+    }
+}
+
+/// Gopher type for a local file or directory.
+pub fn type_for_file(path: &Path) -> Type {
+    if path.is_dir() {
+        Type::Menu
+    } else {
+        type_for_path(&path.to_string_lossy())
+    }
+}
+
+/// Guess the Gopher type of a file or selector from its extension.
+/// Directories (trailing `/`) and Gophermaps are menus, and files
+/// without an extension are assumed to be text.
+pub fn type_for_path(path: &str) -> Type {
+    if path.is_empty() || path.ends_with('/') {
+        return Type::Menu;
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    if name == "gophermap" {
+        return Type::Menu;
+    }
+    let ext = match name.rfind('.') {
+        Some(i) if i > 0 => name[i + 1..].to_ascii_lowercase(),
+        _ => return Type::Text,
+    };
+    match ext.as_ref() {
+        "gph" => Type::Menu,
+        "txt" | "text" | "md" | "markdown" | "org" | "rst" | "csv" | "tsv" | "json" | "xml"
+        | "log" | "conf" | "ini" | "toml" | "yml" | "yaml" | "c" | "h" | "rs" | "py" | "rb"
+        | "sh" | "js" | "css" | "go" | "lisp" | "el" | "diff" | "patch" | "asc" | "nfo" => {
+            Type::Text
+        }
+        "html" | "htm" | "xhtml" => Type::HTML,
+        "gif" => Type::GIF,
+        "png" => Type::PNG,
+        "jpg" | "jpeg" | "bmp" | "webp" | "svg" | "ico" | "tif" | "tiff" => Type::Image,
+        "mp3" | "ogg" | "oga" | "wav" | "flac" | "m4a" | "opus" | "mid" | "midi" => Type::Sound,
+        "pdf" | "doc" | "docx" | "odt" | "rtf" | "epub" | "ps" | "djvu" => Type::Document,
+        "hqx" => Type::Binhex,
+        "uu" | "uue" => Type::UUEncoded,
+        _ => Type::Binary,
+    }
+}
+
+/// Load a `file://` URL. Directories and Gophermaps are returned as
+/// Gophermaps with links pointing to other `file://` URLs, HTML is
+/// returned as-is, and everything else is read as text.
+pub fn load_url(url: &str) -> Result<(Type, String)> {
+    let path = &file_path(url);
+    if !path.exists() {
+        return Err(error!("File not found: {}", path.to_string_lossy()));
+    }
+
+    if path.is_dir() {
+        let map = path.join(GOPHERMAP);
+        return if map.exists() {
+            let raw = String::from_utf8_lossy(&fs::read(&map)?).to_string();
+            Ok((Type::Menu, resolve(&raw, path, path, &file_linker)))
+        } else {
+            let title = path.to_string_lossy();
+            Ok((Type::Menu, for_dir(path, &title, &file_linker)?))
+        };
+    }
+
+    let raw = String::from_utf8_lossy(&fs::read(path)?).to_string();
+    if is_gophermap(path) {
+        let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+        Ok((Type::Menu, resolve(&raw, dir, dir, &file_linker)))
+    } else if type_for_file(path).is_html() {
+        Ok((Type::HTML, raw))
+    } else {
+        Ok((Type::Text, raw))
+    }
+}
+
+/// The `file://` URL for a local path. Characters that would end or
+/// confuse a URL, like `%` and spaces, are percent-encoded.
+pub fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for c in path.to_string_lossy().chars() {
+        if c == '%' || c == '?' || c == '#' || c.is_whitespace() || c.is_control() {
+            for b in c.to_string().bytes() {
+                url.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            url.push(c);
+        }
+    }
+    url
+}
+
+/// The local path a `file://` URL points to, percent-decoded.
+pub fn file_path(url: &str) -> PathBuf {
+    let raw = url.trim_start_matches("file://").as_bytes();
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        let hex = raw
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match hex {
+            Some(b) if raw[i] == b'%' => {
+                bytes.push(b);
+                i += 3;
+            }
+            _ => {
+                bytes.push(raw[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Links local files to `file://` URLs, for browsing Gophermaps on
+/// disk.
+pub fn file_linker(path: &Path) -> (String, String, String) {
+    (
+        format!("URL:{}", file_url(path)),
+        String::new(),
+        String::new(),
+    )
+}
+
+/// Generate a Gophermap listing the files in a directory. Hidden
+/// files and the directory's own `gophermap` are skipped.
+pub fn for_dir(dir: &Path, title: &str, link: Linker) -> Result<String> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| match p.file_name().and_then(|n| n.to_str()) {
+            Some(name) => !name.starts_with('.') && name != GOPHERMAP,
+            None => false,
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let mut out = format!("i{}\r\ni\r\n", title.replace('\t', " "));
+    if entries.is_empty() {
+        out.push_str("iEmpty directory.\r\n");
+    }
+    for path in entries {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (sel, host, port) = link(&path);
+        out.push_str(&format!(
+            "{}{}{}\t{}\t{}\t{}\r\n",
+            type_for_file(&path),
+            name,
+            if path.is_dir() { "/" } else { "" },
+            sel,
+            host,
+            port
+        ));
+    }
+    Ok(out)
+}
+
+/// Rewrite a Gophermap that lives in `dir` so every line is a
+/// complete menu item. Lines without a tab are info text. Link lines
+/// are parsed with the same rules as `menu::parse`, and links without
+/// a host point to local files: absolute selectors are relative to
+/// `root`, everything else to `dir`.
+pub fn resolve(raw: &str, root: &Path, dir: &Path, link: Linker) -> String {
+    let mut out = String::new();
+
+    for line in raw.split_terminator('\n') {
+        let line = line.trim_end_matches('\r');
+        if line == "." {
+            break;
+        }
+
+        if !line.contains('\t') {
+            out.push_str(&format!("i{}\t\terror.host\t1\r\n", line));
+            continue;
+        }
+
+        let typ = match menu::parse_line(0, line) {
+            Some(l) => l.typ,
+            None => continue,
+        };
+        let mut parts = line[1..].split('\t');
+        let text = parts.next().unwrap_or("");
+        let sel = parts.next().unwrap_or("");
+        let host = parts.next().unwrap_or("");
+        let port = parts.next().unwrap_or("");
+
+        if typ.is_info() || !host.is_empty() || sel.starts_with("URL:") {
+            let port = if port.is_empty() { "70" } else { port };
+            out.push_str(&format!("{}{}\t{}\t{}\t{}\r\n", typ, text, sel, host, port));
+            continue;
+        }
+
+        let sel = if sel.is_empty() { text } else { sel };
+        let path = if sel.starts_with('/') {
+            normalize(&root.join(sel.trim_start_matches('/')))
+        } else {
+            normalize(&dir.join(sel))
+        };
+        let (sel, host, port) = link(&path);
+        out.push_str(&format!("{}{}\t{}\t{}\t{}\r\n", typ, text, sel, host, port));
+    }
+
+    out
+}
+
+/// Resolve `.` and `..` in a path without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => out.push(part),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_for_path() {
+        assert_eq!(type_for_path(""), Type::Menu);
+        assert_eq!(type_for_path("/phlog/"), Type::Menu);
+        assert_eq!(type_for_path("/phlog/gophermap"), Type::Menu);
+        assert_eq!(type_for_path("bookmarks.gph"), Type::Menu);
+        assert_eq!(type_for_path("/README"), Type::Text);
+        assert_eq!(type_for_path("/.profile"), Type::Text);
+        assert_eq!(type_for_path("/notes.TXT"), Type::Text);
+        assert_eq!(type_for_path("/index.html"), Type::HTML);
+        assert_eq!(type_for_path("/cat.gif"), Type::GIF);
+        assert_eq!(type_for_path("/cat.jpeg"), Type::Image);
+        assert_eq!(type_for_path("/song.ogg"), Type::Sound);
+        assert_eq!(type_for_path("/paper.pdf"), Type::Document);
+        assert_eq!(type_for_path("/phetch.tgz"), Type::Binary);
+    }
+
+    fn linker(path: &Path) -> (String, String, String) {
+        let sel = path.strip_prefix("/srv").unwrap_or(path);
+        (
+            format!("/{}", sel.to_string_lossy()),
+            "localhost".into(),
+            "7070".into(),
+        )
+    }
+
+    #[test]
+    fn test_resolve() {
+        let raw = "Welcome!
+1Phlog\tphlog
+0About\t/about.txt
+1Up\t../
+0Remote\t/file.txt\texample.org
+hWeb\tURL:https://example.com
+Xbogus\tline
+.
+iafter the end";
+        let map = resolve(raw, Path::new("/srv"), Path::new("/srv/docs"), &linker);
+        assert_eq!(
+            map,
+            "iWelcome!\t\terror.host\t1\r
+1Phlog\t/docs/phlog\tlocalhost\t7070\r
+0About\t/about.txt\tlocalhost\t7070\r
+1Up\t/\tlocalhost\t7070\r
+0Remote\t/file.txt\texample.org\t70\r
+hWeb\tURL:https://example.com\t\t70\r
+"
+        );
+    }
+
+    #[test]
+    fn test_file_links() {
+        let map = resolve(
+            "1Phlog\tphlog\n0Notes\t/notes.txt",
+            Path::new("/home/gopher"),
+            Path::new("/home/gopher/docs"),
+            &file_linker,
+        );
+        let menu = menu::parse("test", map);
+        assert_eq!(
+            menu.lines[0].url(&menu.raw),
+            "file:///home/gopher/docs/phlog"
+        );
+        assert_eq!(
+            menu.lines[1].url(&menu.raw),
+            "file:///home/gopher/notes.txt"
+        );
+    }
+
+    #[test]
+    fn test_file_url() {
+        let path = Path::new("/home/gopher/50% off/café.txt");
+        let url = file_url(path);
+        assert_eq!(url, "file:///home/gopher/50%25%20off/café.txt");
+        assert_eq!(file_path(&url), path);
+        assert_eq!(file_path("file:///a%2"), Path::new("/a%2"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/a/../../b")), Path::new("/b"));
+    }
+
+    #[test]
+    fn test_is_gophermap() {
+        assert!(is_gophermap(Path::new("/srv/gophermap")));
+        assert!(is_gophermap(Path::new("bookmarks.gph")));
+        assert!(!is_gophermap(Path::new("/srv/notes.txt")));
+    }
+}
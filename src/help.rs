@@ -8,7 +8,10 @@ pub fn lookup(name: &str) -> Option<String> {
     Some(match name {
         "" | "/" | "home" | "home/" => format!("{}{}", HEADER, START),
         "history" => history::as_raw_menu(),
-        "bookmarks" => bookmarks::as_raw_menu(),
+        "bookmarks" | "bookmarks/" => bookmarks::as_raw_menu(None),
+        folder if folder.starts_with("bookmarks/") => {
+            bookmarks::as_raw_menu(Some(folder.trim_start_matches("bookmarks/")))
+        }
         "help/config" => format!("{}{}", HEADER, CONFIG),
         "help/keys" => format!("{}{}", HEADER, KEYS),
         "help/nav" => format!("{}{}", HEADER, NAV),
@@ -113,15 +116,35 @@ ip or k     select prev link
 in or j     select next link
 i
 ig          go to gopher url
+iU          go up to parent menu
+iR          go to server root
 iu          edit url
 iy          copy url
 i
 ib          show bookmarks
 is          save bookmark
+id          delete bookmark
+im          move bookmark
+il          rename bookmark
+iF          move bookmark to folder
 ia          show history
-i
+iA          show history tree
+io          find in history and
+i           bookmarks
+iP          toggle private mode
+i
+it          open link in new tab
+itab        next tab
+ishift-tab  previous tab
+iT          list tabs
+ix          close tab
+i
+ic          check links on page
+iI          show server info
+ie          export page to file
 ir          view raw source
 iw          toggle wide mode
+iV          toggle split view
 iq          quit phetch
 ih          show help
 i
@@ -179,7 +202,34 @@ iif ~/.config/phetch/ exists,
 ibookmarks will be saved to
 i~/.config/phetch/bookmarks.gph
 i
-ipress b to view them.
+is asks for a label, suggesting
+ithe page's title.
+i
+ipress b to view them. on that
+ipage, select a bookmark and:
+i
+id      delete it
+im      move it to a new position
+il      rename it
+iF      move it to a folder
+i
+ifolders show up at the top of
+ithe bookmarks page. they're made
+iwhen you move something into one
+iand removed once they're empty.
+i
+iyou can also edit them from
+ithe command line:
+i
+iphetch bookmarks list
+iphetch bookmarks add URL [LABEL]
+iphetch bookmarks rm N
+iphetch bookmarks mv N M
+iphetch bookmarks export [FORMAT]
+iphetch bookmarks import FILE [FORMAT]
+i
+iformats: gophermap, vf1, lynx,
+inetscape, urls
 i
 ithe clipboard function uses:
 i
@@ -193,13 +243,37 @@ i
 iif you create a history.gph
 ifile in ~/.config/phetch/,
 ieach gopher url you open will
-ibe stored there.
-i
-inew urls are appended to the
-ibottom, but loaded in reverse
-iorder, so you'll see the most
-irecently visited pages first
-iwhen you press the a key.
+ibe stored there, along with
+iwhen you opened it and how
+imany times. opening the same
+iurl twice in a row just bumps
+iits count.
+i
+ipress the a key to see your
+ihistory, most recent first and
+igrouped by day.
+i
+ionce the file has more than
+i`history_max` pages (5000 by
+idefault), it's compacted: each
+iurl is kept once, and the
+ioldest ones are dropped.
+i
+inothing is saved in private
+imode, which you can start with
+i--private or toggle with the
+iP key.
+i
+ipress the o key to search your
+ihistory and bookmarks at once.
+ityping narrows down the list:
+ieach word just needs its
+iletters to show up, in order,
+iin a page's title or url. pages
+iyou visit often or recently,
+iand bookmarks, come first.
+iuse up and down to pick one and
+ienter to open it.
 i
 ifeel free to edit your history
 ifile directly, or share it
@@ -237,6 +311,30 @@ iwide no
 i
 i# show emoji status indicators
 iemoji no
+i
+i# most pages to keep in history,
+i# or 0 for no limit
+ihistory_max 5000
+i
+i# reopen the pages you had open
+i# last time you quit
+irestore no
+i
+i# preview the selected link next
+i# to menus on wide screens
+isplit no
+i
+i# fetch links in the background
+i# before they're opened
+iprefetch no
+i
+i# open types with other programs.
+i# %f is a temp file, %u the url,
+i# %h the host and %p the port.
+ihandler s mpv %f
+ihandler I feh %f
+ihandler h w3m %u
+ihandler telnet ssh %h
 ";
 
 const TYPES: &str = "
@@ -1,13 +1,38 @@
 //! phetch will append every URL visited to a Gophermap if a
 //! `HISTORY_FILE` in `~/.config/phetch/` exists. This file must be
 //! manually created by the user for history tracking to engage.
+//!
+//! Each line is a regular Gophermap link with two extra fields: when
+//! it was last visited, in seconds since the epoch, and how many
+//! times. Opening the same URL again just bumps the last line. Once
+//! the file grows past the `history_max` config option, it's
+//! compacted: each URL is kept only once, with all its visits, and
+//! the oldest entries are dropped.
 
-use crate::phetchdir;
-use std::io::{BufRead, Result};
+use crate::{menu, phetchdir};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, Read, Result, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 /// History only works if you've created ~/.config/phetch/history.gph manually.
 const HISTORY_FILE: &str = "history.gph";
 
+/// Default number of entries to keep.
+pub const DEFAULT_MAX: usize = 5000;
+
+/// Entries are saved from background threads, one at a time. Holds
+/// how many entries the history file has, once they've been counted.
+static LOCK: Mutex<Option<usize>> = Mutex::new(None);
+
+/// How much of the end of the history file to read at a time when
+/// looking for the last line.
+const TAIL_CHUNK: u64 = 1024;
+
 macro_rules! file_missing_fmt {
     () => {
         "i\r\ni\r
@@ -18,6 +43,50 @@ i\r\nimkdir -p {dir} && touch {file}"
     };
 }
 
+/// A page in the history file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Page title.
+    pub label: String,
+    /// Gopher URL.
+    pub url: String,
+    /// When it was last visited, in seconds since the epoch. 0 for
+    /// entries saved by older versions of phetch.
+    pub time: u64,
+    /// How many times it's been visited.
+    pub visits: usize,
+}
+
+impl Entry {
+    /// Parse a line of the history file.
+    fn parse(line: &str) -> Option<Entry> {
+        let l = menu::parse_line(0, line).filter(|l| l.typ.is_link())?;
+        let mut extra = line.split('\t').skip(4);
+        Some(Entry {
+            label: l.text(line).to_string(),
+            url: l.url(line),
+            time: extra
+                .next()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(0),
+            visits: extra
+                .next()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(1),
+        })
+    }
+
+    /// Line for the history file, without the trailing CRLF.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}",
+            phetchdir::menu_line(&self.label, &self.url),
+            self.time,
+            self.visits
+        )
+    }
+}
+
 /// Returns history as a Gophermap.
 pub fn as_raw_menu() -> String {
     let homepath = format!("{}{}", phetchdir::DIR, HISTORY_FILE);
@@ -41,28 +110,369 @@ pub fn as_raw_menu() -> String {
         );
     }
 
-    let mut out = vec![format!("i{}:\r\ni", homepath)];
-    match phetchdir::load(HISTORY_FILE) {
-        Ok(reader) => {
-            let mut lines = reader.lines();
-            while let Some(Ok(line)) = lines.next() {
-                out.insert(1, line);
-            }
+    let header = format!("i{}:\r\n", homepath);
+    match load() {
+        Ok(entries) if entries.is_empty() => format!("{}i\r\niNo history entries yet.", header),
+        Ok(entries) => format!("{}{}", header, render(&entries, now())),
+        Err(e) => format!("{}i\r\n3{}", header, e),
+    }
+}
+
+/// Gophermap of entries, most recent first, under a heading for each
+/// day they were visited.
+fn render(entries: &[Entry], now: u64) -> String {
+    let today = Date::local(now);
+    let yesterday = Date::local(now.saturating_sub(24 * 60 * 60));
+    let mut out = String::new();
+    let mut day = None;
+    for entry in entries.iter().rev() {
+        let date = if entry.time == 0 {
+            None
+        } else {
+            Some(Date::local(entry.time))
+        };
+        let this_day = date.map(|d| (d.year, d.month, d.day));
+        if day != Some(this_day) {
+            day = Some(this_day);
+            let heading = match date {
+                None => "Earlier".to_string(),
+                Some(d) if d.same_day(&today) => "Today".to_string(),
+                Some(d) if d.same_day(&yesterday) => "Yesterday".to_string(),
+                Some(d) => d.to_string(),
+            };
+            out.push_str(&format!("i\r\ni{}\r\n", heading));
+        }
+        let mut label = match date {
+            Some(d) => format!("{:02}:{:02}  {}", d.hour, d.minute, entry.label),
+            None => entry.label.clone(),
+        };
+        if entry.visits > 1 {
+            label.push_str(&format!(" ({} visits)", entry.visits));
+        }
+        out.push_str(&phetchdir::menu_line(&label, &entry.url));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Every entry in the history file, oldest first. An empty list if
+/// there's no history file.
+pub fn load() -> Result<Vec<Entry>> {
+    if !phetchdir::exists(HISTORY_FILE) {
+        return Ok(vec![]);
+    }
+    let mut entries = vec![];
+    for line in phetchdir::load(HISTORY_FILE)?.lines() {
+        if let Some(entry) = Entry::parse(line?.trim_end_matches('\r')) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Save a single history entry if the history file exists. The file
+/// is compacted once it has more than `max` entries, unless `max` is 0.
+/// Only the last line is read, unless it's time to compact.
+pub fn save(label: &str, url: &str, max: usize) -> Result<()> {
+    let path = match phetchdir::path() {
+        Ok(dir) => dir.join(HISTORY_FILE),
+        Err(e) => return Err(error!("History file doesn't exist: {}", e)),
+    };
+
+    let mut count = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let last = match File::open(&path) {
+        Ok(mut file) => last_line(&mut file)?,
+        Err(_) => None,
+    };
+    let mut entries: Vec<Entry> = last.iter().filter_map(|(_, l)| Entry::parse(l)).collect();
+    let time = now();
+    if !record(&mut entries, label, url, time) {
+        let offset = last.map_or(0, |(offset, _)| offset);
+        return replace_from(&path, offset, &entries[0].to_line());
+    }
+
+    let entry = entries.pop().expect("just recorded");
+    let n = match *count {
+        Some(n) => n,
+        None => load()?.len(),
+    };
+    if max == 0 || n < max {
+        *count = Some(n + 1);
+        return append(&entry.to_line());
+    }
+
+    let mut entries = load()?;
+    entries.push(entry);
+    compact(&mut entries, max);
+    *count = Some(entries.len());
+    let mut out = String::new();
+    for entry in &entries {
+        out.push_str(&entry.to_line());
+        out.push_str("\r\n");
+    }
+    phetchdir::save(HISTORY_FILE, &out)
+}
+
+/// The last line of a file and where it starts, reading backwards
+/// from the end so a long history isn't read just to find it.
+fn last_line<F: Read + Seek>(file: &mut F) -> Result<Option<(u64, String)>> {
+    let mut start = file.seek(SeekFrom::End(0))?;
+    let mut tail: Vec<u8> = vec![];
+    loop {
+        let end = tail
+            .iter()
+            .rposition(|b| *b != b'\r' && *b != b'\n')
+            .map_or(0, |i| i + 1);
+        let body = &tail[..end];
+        if let Some(i) = body.iter().rposition(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&body[i + 1..]).to_string();
+            return Ok(Some((start + i as u64 + 1, line)));
+        }
+        if start == 0 {
+            let line = String::from_utf8_lossy(body).to_string();
+            return Ok(Some((0, line)).filter(|(_, line)| !line.is_empty()));
+        }
+        let size = start.min(TAIL_CHUNK);
+        start -= size;
+        let mut chunk = vec![0; size as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend(tail);
+        tail = chunk;
+    }
+}
+
+/// Replace everything in the history file from `offset` on with a
+/// line.
+fn replace_from(path: &Path, offset: u64, line: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| error!("Can't open file for writing: {:?}: {}", path, e))?;
+    file.set_len(offset)?;
+    file.seek(SeekFrom::Start(offset))?;
+    write!(file, "{}\r\n", line)
+}
+
+/// Add a line to the end of the history file.
+fn append(line: &str) -> Result<()> {
+    let path = phetchdir::path()?.join(HISTORY_FILE);
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .map_err(|e| error!("Can't open file for writing: {:?}: {}", path, e))?;
+    write!(file, "{}\r\n", line)
+}
+
+/// Record a visit to `url`. Visiting the same URL as last time just
+/// bumps its count. Returns true if a new entry was added.
+fn record(entries: &mut Vec<Entry>, label: &str, url: &str, time: u64) -> bool {
+    if let Some(last) = entries.last_mut() {
+        if last.url == url {
+            last.label = label.to_string();
+            last.time = time;
+            last.visits += 1;
+            return false;
+        }
+    }
+    entries.push(Entry {
+        label: label.to_string(),
+        url: url.to_string(),
+        time,
+        visits: 1,
+    });
+    true
+}
+
+/// Keep only the latest entry for each URL, with the visits from all
+/// of them, then drop the oldest entries until there's some room
+/// under `max` so we aren't compacting on every visit.
+fn compact(entries: &mut Vec<Entry>, max: usize) {
+    let mut latest: HashMap<String, usize> = HashMap::new();
+    let mut kept: Vec<Entry> = vec![];
+    for entry in entries.drain(..).rev() {
+        if let Some(&i) = latest.get(&entry.url) {
+            kept[i].visits += entry.visits;
+        } else {
+            latest.insert(entry.url.clone(), kept.len());
+            kept.push(entry);
+        }
+    }
+    kept.truncate(max - max / 10);
+    kept.reverse();
+    *entries = kept;
+}
+
+/// Seconds since the epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A point in time in the local timezone.
+#[derive(Debug, Clone, Copy)]
+struct Date {
+    year: i32,
+    month: usize,
+    day: u32,
+    weekday: usize,
+    hour: u32,
+    minute: u32,
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+impl Date {
+    /// Local date and time for seconds since the epoch.
+    fn local(time: u64) -> Date {
+        let t = time as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe { libc::localtime_r(&t, &mut tm) };
+        Date {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon.max(0) as usize % 12,
+            day: tm.tm_mday.max(0) as u32,
+            weekday: tm.tm_wday.max(0) as usize % 7,
+            hour: tm.tm_hour.max(0) as u32,
+            minute: tm.tm_min.max(0) as u32,
         }
-        Err(e) => out.push(format!("3{}", e)),
     }
 
-    if out.len() == 1 {
-        out.insert(1, "iNo history entries yet.".to_string());
+    fn same_day(&self, other: &Date) -> bool {
+        (self.year, self.month, self.day) == (other.year, other.month, other.day)
     }
-    out.join("\r\n")
 }
 
-/// Save a single history entry if the history file exists.
-pub fn save(label: &str, url: &str) -> Result<()> {
-    if let Err(e) = phetchdir::path() {
-        return Err(error!("History file doesn't exist: {}", e));
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {} {}, {}",
+            WEEKDAYS[self.weekday], MONTHS[self.month], self.day, self.year
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, time: u64, visits: usize) -> Entry {
+        Entry {
+            label: url.to_string(),
+            url: format!("gopher://{}", url),
+            time,
+            visits,
+        }
     }
 
-    phetchdir::append(HISTORY_FILE, label, url)
+    #[test]
+    fn test_parse() {
+        // saved by older versions
+        let old = Entry::parse("1SDF\t/\tsdf.org\t70").unwrap();
+        assert_eq!(old.url, "gopher://sdf.org");
+        assert_eq!((old.time, old.visits), (0, 1));
+
+        let new = Entry::parse("0Tips\t/tips.txt\tsdf.org\t70\t1700000000\t3").unwrap();
+        assert_eq!(new.label, "Tips");
+        assert_eq!(new.url, "gopher://sdf.org/0/tips.txt");
+        assert_eq!((new.time, new.visits), (1_700_000_000, 3));
+        assert_eq!(Entry::parse(&new.to_line()), Some(new));
+
+        assert_eq!(Entry::parse("iJust text"), None);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut entries = vec![];
+        assert!(record(&mut entries, "SDF", "gopher://sdf.org", 10));
+        assert!(!record(&mut entries, "SDF!", "gopher://sdf.org", 20));
+        assert!(record(&mut entries, "Lawn", "gopher://bitreich.org", 30));
+        assert!(record(&mut entries, "SDF", "gopher://sdf.org", 40));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].label, "SDF!");
+        assert_eq!((entries[0].time, entries[0].visits), (20, 2));
+        assert_eq!(entries[2].visits, 1);
+    }
+
+    #[test]
+    fn test_last_line() {
+        use std::io::Cursor;
+        let last = |s: &str| last_line(&mut Cursor::new(s.as_bytes())).unwrap();
+        assert_eq!(last(""), None);
+        assert_eq!(last("one\r\n"), Some((0, "one".into())));
+        assert_eq!(last("one\r\ntwo\r\n"), Some((5, "two".into())));
+        assert_eq!(last("one\r\ntwo"), Some((5, "two".into())));
+
+        // longer than a chunk
+        let long = "x".repeat(TAIL_CHUNK as usize * 2);
+        let raw = format!("one\r\n{}\r\n", long);
+        assert_eq!(last(&raw), Some((5, long)));
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut entries = vec![
+            entry("a", 1, 1),
+            entry("b", 2, 2),
+            entry("a", 3, 1),
+            entry("c", 4, 1),
+            entry("b", 5, 1),
+        ];
+        compact(&mut entries, 10);
+        assert_eq!(
+            entries,
+            vec![entry("a", 3, 2), entry("c", 4, 1), entry("b", 5, 3)]
+        );
+
+        let mut entries: Vec<Entry> = (0..20).map(|i| entry(&i.to_string(), i, 1)).collect();
+        compact(&mut entries, 10);
+        assert_eq!(entries.len(), 9);
+        assert_eq!(entries[0].url, "gopher://11");
+    }
+
+    #[test]
+    fn test_render() {
+        let now = now();
+        let days_ago = now - 3 * 24 * 60 * 60;
+        let entries = vec![
+            entry("old", 0, 1),
+            entry("before", days_ago, 1),
+            entry("sdf.org", now, 2),
+        ];
+        let out = render(&entries, now);
+        let lines: Vec<&str> = out.lines().map(|l| l.trim_end_matches('\r')).collect();
+        assert_eq!(lines[1], "iToday");
+        assert!(lines[2].starts_with('1'));
+        assert!(lines[2].contains("  sdf.org (2 visits)\t"));
+        assert_eq!(lines[4], format!("i{}", Date::local(days_ago)));
+        assert!(lines[5].contains("  before\t"));
+        assert_eq!(&lines[7..], ["iEarlier", "1old\t\told\t70"]);
+    }
 }
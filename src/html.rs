@@ -0,0 +1,743 @@
+//! A tiny HTML renderer for Gopher-served `h` documents. It's not
+//! trying to be a web browser: paragraphs, headings, lists, and
+//! `<pre>` blocks become wrapped text, and each `<a>` link is
+//! numbered in the text and listed right after its paragraph.
+//!
+//! The output is a Gophermap, so the document can be shown (and its
+//! links followed) using the regular Menu view. Links are emitted in
+//! the same order they are numbered, which means the `[3]` in the
+//! text is also link number 3 in the Menu.
+
+use crate::{gopher, gophermap};
+use std::path::PathBuf;
+
+/// Text is wrapped at this column. Menus indent info lines by a few
+/// columns, so we leave some room.
+const WRAP_COLS: usize = 70;
+
+/// Pieces of an HTML document.
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    /// Text between tags. Entities are still encoded.
+    Text(&'a str),
+    /// Opening tag: (lowercase name, href attribute)
+    Open(String, Option<String>),
+    /// Closing tag, lowercase name.
+    Close(String),
+}
+
+/// Converts an HTML document into a Gophermap that can be displayed
+/// as a Menu. `url` is the Gopher URL the document was fetched from,
+/// and is used to resolve relative links.
+pub fn to_gophermap(url: &str, html: &str) -> String {
+    let mut renderer = Renderer::new(url);
+    for token in tokenize(html) {
+        renderer.push(token);
+    }
+    renderer.finish()
+}
+
+/// Every `<a href>` link in an HTML document, as (text, href) pairs
+/// in order. Whitespace in the text is collapsed. Used to import
+/// bookmarks saved as HTML by other browsers.
+pub fn links(html: &str) -> Vec<(String, String)> {
+    let mut links = vec![];
+    let mut current: Option<(String, String)> = None;
+    for token in tokenize(html) {
+        match token {
+            Token::Open(name, Some(href)) if name == "a" => {
+                current = Some((String::new(), href));
+            }
+            Token::Text(text) => {
+                if let Some((label, _)) = current.as_mut() {
+                    label.push_str(&decode_entities(text));
+                }
+            }
+            Token::Close(name) if name == "a" => {
+                if let Some((label, href)) = current.take() {
+                    let label = label.split_whitespace().collect::<Vec<_>>().join(" ");
+                    links.push((label, href));
+                }
+            }
+            _ => {}
+        }
+    }
+    links
+}
+
+/// Split an HTML document into text and tags. Comments, doctypes,
+/// and anything else we don't understand are dropped. A `<` that's
+/// never closed is just text.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(i) => &rest[i + 3..],
+                None => "",
+            };
+        } else if rest.starts_with('<') && is_tag_start(&rest[1..]) {
+            let end = match rest.find('>') {
+                Some(i) => i,
+                None => {
+                    tokens.push(Token::Text(rest));
+                    break;
+                }
+            };
+            if let Some(token) = parse_tag(&rest[1..end]) {
+                tokens.push(token);
+            }
+            rest = &rest[end + 1..];
+        } else {
+            // a lone < is just text
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..]
+                .find('<')
+                .map(|i| i + first)
+                .unwrap_or_else(|| rest.len());
+            tokens.push(Token::Text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+
+    tokens
+}
+
+/// Does this look like the inside of a tag? (After the <)
+fn is_tag_start(s: &str) -> bool {
+    match s.chars().next() {
+        Some(c) => c.is_ascii_alphabetic() || c == '/' || c == '!',
+        None => false,
+    }
+}
+
+/// Parse the inside of a tag, ex: `a href="/about"` or `/p`
+fn parse_tag(tag: &str) -> Option<Token<'static>> {
+    let (closing, tag) = match tag.strip_prefix('/') {
+        Some(tag) => (true, tag),
+        None => (false, tag),
+    };
+    let name = tag
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    if closing {
+        Some(Token::Close(name))
+    } else {
+        let href = if name == "a" { attr(tag, "href") } else { None };
+        Some(Token::Open(name, href))
+    }
+}
+
+/// Find the value of an attribute in the inside of a tag.
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(name) {
+        let start = from + i;
+        from = start + name.len();
+        // must be its own word, ex: not `data-href`
+        if !lower[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let rest = tag[from..].trim_start();
+        if !rest.starts_with('=') {
+            continue;
+        }
+        let rest = rest[1..].trim_start();
+        let value = match rest.chars().next() {
+            Some(q) if q == '"' || q == '\'' => rest[1..].split(q).next().unwrap_or(""),
+            _ => rest.split(char::is_whitespace).next().unwrap_or(""),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Replace HTML entities like `&amp;` and `&#39;` with their chars.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = match rest.find(';') {
+            Some(end) if end < 10 => end,
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "copy" => Some('©'),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "laquo" => Some('«'),
+            "raquo" => Some('»'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            e if e.starts_with("#x") || e.starts_with("#X") => u32::from_str_radix(&e[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            e if e.starts_with('#') => e[1..].parse().ok().and_then(std::char::from_u32),
+            _ => None,
+        };
+        if let Some(c) = decoded {
+            out.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            out.push('&');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Turns a stream of Tokens into Gophermap lines.
+struct Renderer {
+    /// Gopher URL of the document, for resolving links.
+    url: String,
+    /// Finished Gophermap lines.
+    lines: Vec<String>,
+    /// Text of the block we're currently in.
+    text: String,
+    /// Link lines to print after the current block.
+    links: Vec<String>,
+    /// Number of links so far.
+    count: usize,
+    /// href of the <a> we're in, if any.
+    href: Option<String>,
+    /// Text of the <a> we're in.
+    label: String,
+    /// Heading level of the block we're in, if any.
+    heading: usize,
+    /// Inside a <pre>?
+    pre: bool,
+    /// Inside a <title>?
+    title: bool,
+    /// Inside <script> or <style>? Their content is skipped.
+    skip: usize,
+    /// Nested lists. None for <ul>, or the item count for <ol>.
+    lists: Vec<Option<usize>>,
+    /// Bullet for the first line of the current list item.
+    bullet: String,
+}
+
+impl Renderer {
+    fn new(url: &str) -> Renderer {
+        Renderer {
+            url: url.into(),
+            lines: vec![],
+            text: String::new(),
+            links: vec![],
+            count: 0,
+            href: None,
+            label: String::new(),
+            heading: 0,
+            pre: false,
+            title: false,
+            skip: 0,
+            lists: vec![],
+            bullet: String::new(),
+        }
+    }
+
+    /// Process a single Token.
+    fn push(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.push_text(text),
+            Token::Open(tag, href) => self.open(&tag, href),
+            Token::Close(tag) => self.close(&tag),
+        }
+    }
+
+    /// Returns the finished Gophermap.
+    fn finish(mut self) -> String {
+        self.flush();
+        while self.lines.last().map(|l| l == "i").unwrap_or(false) {
+            self.lines.pop();
+        }
+        let mut out = self.lines.join("\r\n");
+        out.push_str("\r\n");
+        out
+    }
+
+    /// Add text to the current block, collapsing whitespace unless
+    /// we're in a <pre>. Text in an <a> is also its label.
+    fn push_text(&mut self, text: &str) {
+        if self.skip > 0 {
+            return;
+        }
+        let text = decode_entities(text);
+        if self.pre {
+            self.text.push_str(&text);
+            return;
+        }
+        for c in text.chars() {
+            let c = if c.is_whitespace() { ' ' } else { c };
+            if c == ' ' {
+                if !self.text.is_empty() && !self.text.ends_with(' ') {
+                    self.text.push(' ');
+                }
+                if self.href.is_some() && !self.label.is_empty() && !self.label.ends_with(' ') {
+                    self.label.push(' ');
+                }
+            } else {
+                self.text.push(c);
+                if self.href.is_some() {
+                    self.label.push(c);
+                }
+            }
+        }
+    }
+
+    /// Start an element: begin a new block, heading, list item, or
+    /// link, or start skipping a <script> or <style>.
+    fn open(&mut self, tag: &str, href: Option<String>) {
+        match tag {
+            "script" | "style" | "noscript" | "template" => self.skip += 1,
+            "title" => {
+                self.flush();
+                self.title = true;
+            }
+            "a" => {
+                self.href = href;
+                self.label.clear();
+            }
+            "br" => {
+                if self.pre {
+                    self.text.push('\n');
+                } else {
+                    self.flush();
+                }
+            }
+            "p" | "blockquote" | "table" | "dl" | "figure" => {
+                self.flush();
+                self.blank();
+            }
+            "div" | "section" | "article" | "header" | "footer" | "nav" | "main" | "tr" | "dt"
+            | "dd" | "form" => self.flush(),
+            "td" | "th" => self.push_text(" "),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.blank();
+                self.heading = tag[1..].parse().unwrap_or(1);
+            }
+            "ul" | "ol" => {
+                self.flush();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+                self.lists.push(if tag == "ol" { Some(0) } else { None });
+            }
+            "li" => {
+                self.flush();
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                self.bullet = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}{}. ", indent, n)
+                    }
+                    _ => format!("{}* ", indent),
+                };
+            }
+            "pre" => {
+                self.flush();
+                self.blank();
+                self.pre = true;
+            }
+            "hr" => {
+                self.flush();
+                self.blank();
+                self.lines.push(format!("i{}", "-".repeat(WRAP_COLS / 2)));
+                self.blank();
+            }
+            _ => {}
+        }
+    }
+
+    /// End an element, flushing its block or adding its link.
+    fn close(&mut self, tag: &str) {
+        match tag {
+            "script" | "style" | "noscript" | "template" => self.skip = self.skip.saturating_sub(1),
+            "title" => {
+                let title = self.text.trim().to_string();
+                self.text.clear();
+                self.title = false;
+                if !title.is_empty() {
+                    self.push_heading(1, &title);
+                }
+            }
+            "a" => {
+                if let Some(href) = self.href.take() {
+                    let label = self.label.trim().to_string();
+                    let label = if label.is_empty() { &href } else { &label };
+                    if let Some(line) = self.link_line(label, &href) {
+                        self.count += 1;
+                        if !self.text.ends_with(' ') && !self.text.is_empty() {
+                            self.text.push(' ');
+                        }
+                        self.text.push_str(&format!("[{}]", self.count));
+                        self.links.push(line);
+                    }
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.text.trim().to_string();
+                let level = self.heading;
+                self.text.clear();
+                self.heading = 0;
+                if !text.is_empty() {
+                    self.push_heading(level, &text);
+                }
+                self.push_links();
+            }
+            "p" | "blockquote" | "table" | "dl" | "figure" => {
+                self.flush();
+                self.blank();
+            }
+            "ul" | "ol" => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            "li" | "div" | "section" | "article" | "header" | "footer" | "nav" | "main" | "tr"
+            | "dt" | "dd" | "form" => self.flush(),
+            "pre" => {
+                self.flush();
+                self.pre = false;
+                self.blank();
+            }
+            _ => {}
+        }
+    }
+
+    /// Add a heading, underlined if it's h1 or h2.
+    fn push_heading(&mut self, level: usize, text: &str) {
+        self.blank();
+        for line in wrap(text, WRAP_COLS) {
+            self.lines.push(format!("i{}", line));
+        }
+        let width = text.chars().count().min(WRAP_COLS);
+        match level {
+            1 => self.lines.push(format!("i{}", "=".repeat(width))),
+            2 => self.lines.push(format!("i{}", "-".repeat(width))),
+            _ => {}
+        }
+        self.blank();
+    }
+
+    /// Add a blank line, unless we just did.
+    fn blank(&mut self) {
+        if self.lines.last().map(|l| l != "i").unwrap_or(false) {
+            self.lines.push("i".into());
+        }
+    }
+
+    /// Add the current block's text as wrapped lines, followed by
+    /// its links.
+    fn flush(&mut self) {
+        if self.title || self.heading > 0 {
+            return;
+        }
+        let text = std::mem::take(&mut self.text);
+        if self.pre {
+            for line in text.trim_matches('\n').split('\n') {
+                let line = line.trim_end_matches('\r').replace('\t', "    ");
+                self.lines.push(format!("i{}", line));
+            }
+        } else if !text.trim().is_empty() {
+            let bullet = std::mem::take(&mut self.bullet);
+            let indent = " ".repeat(bullet.chars().count());
+            let width = WRAP_COLS.saturating_sub(indent.len()).max(20);
+            for (i, line) in wrap(text.trim(), width).iter().enumerate() {
+                let prefix = if i == 0 { &bullet } else { &indent };
+                self.lines.push(format!("i{}{}", prefix, line));
+            }
+        }
+        self.push_links();
+    }
+
+    /// Add the link lines we've been saving up.
+    fn push_links(&mut self) {
+        self.lines.append(&mut self.links);
+    }
+
+    /// Build a Gophermap line for an <a> link, if it goes anywhere.
+    fn link_line(&self, label: &str, href: &str) -> Option<String> {
+        let href = href.trim();
+        if href.is_empty() || href.starts_with('#') || href.starts_with("javascript:") {
+            return None;
+        }
+        let label = label.replace('\t', " ");
+
+        if href.starts_with("gopher://") {
+            let u = gopher::parse_url(href);
+            return Some(format!(
+                "{}{}\t{}\t{}\t{}",
+                u.typ, label, u.sel, u.host, u.port
+            ));
+        }
+
+        // local HTML files link to other local files
+        if self.url.starts_with("file://") && !has_scheme(href) {
+            let base = gophermap::file_path(&self.url);
+            let path = PathBuf::from(resolve(&base.to_string_lossy(), href));
+            let typ = gophermap::type_for_file(&path);
+            let url = gophermap::file_url(&path);
+            return Some(format!("{}{}\tURL:{}\t\t", typ, label, url));
+        }
+
+        let base = gopher::parse_url(&self.url);
+        if has_scheme(href) {
+            return Some(format!(
+                "h{}\tURL:{}\t{}\t{}",
+                label, href, base.host, base.port
+            ));
+        }
+
+        let sel = resolve(base.sel, href);
+        let typ = gophermap::type_for_path(&sel);
+        Some(format!(
+            "{}{}\t{}\t{}\t{}",
+            typ, label, sel, base.host, base.port
+        ))
+    }
+}
+
+/// Does this href start with a scheme, like `https:` or `mailto:`?
+fn has_scheme(href: &str) -> bool {
+    if let Some(i) = href.find(':') {
+        let scheme = &href[..i];
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+    } else {
+        false
+    }
+}
+
+/// Resolve a relative href against the selector of the current page.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or("");
+    let path = if href.starts_with('/') {
+        href.to_string()
+    } else {
+        let dir = match base.rfind('/') {
+            Some(i) => &base[..=i],
+            None => "/",
+        };
+        format!("{}{}", dir, href)
+    };
+
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split('/').skip(1) {
+        match part {
+            "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    let mut out = format!("/{}", parts.join("/"));
+    if (path.ends_with("/.") || path.ends_with("/..")) && !out.ends_with('/') {
+        out.push('/');
+    }
+    out
+}
+
+/// Wrap text to the given width, breaking on spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut len = 0;
+    for word in text.split(' ').filter(|w| !w.is_empty()) {
+        let wlen = word.chars().count();
+        if len > 0 && len + 1 + wlen > width {
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+        }
+        if len > 0 {
+            line.push(' ');
+            len += 1;
+        }
+        line.push_str(word);
+        len += wlen;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::menu;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("<!DOCTYPE html><p class=x>Hi <a HREF='/x'>there</a> 1 < 2</p>");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Open("p".into(), None),
+                Token::Text("Hi "),
+                Token::Open("a".into(), Some("/x".into())),
+                Token::Text("there"),
+                Token::Close("a".into()),
+                Token::Text(" 1 "),
+                Token::Text("< 2"),
+                Token::Close("p".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_tag() {
+        let tokens = tokenize("<p>a <b and the rest");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Open("p".into(), None),
+                Token::Text("a "),
+                Token::Text("<b and the rest")
+            ]
+        );
+        let map = to_gophermap("gopher://sdf.org/h/x.html", "<p>a <b and the rest");
+        assert!(map.contains("a <b and the rest"));
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let tokens = tokenize("<p>héllo</p>é<b>x</b>");
+        assert_eq!(tokens[1], Token::Text("héllo"));
+        assert_eq!(tokens[3], Token::Text("é"));
+        let map = to_gophermap("gopher://sdf.org/h/x.html", "<p>héllo</p>é<b>x</b>");
+        assert!(map.contains("héllo"));
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &lt;3 &#65;&#x42;"),
+            "a & b <3 AB"
+        );
+        assert_eq!(decode_entities("AT&T &bogus; &"), "AT&T &bogus; &");
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("/docs/index.html", "about.html"),
+            "/docs/about.html"
+        );
+        assert_eq!(resolve("/docs/index.html", "../img/a.png"), "/img/a.png");
+        assert_eq!(resolve("/docs/index.html", "/top.txt"), "/top.txt");
+        assert_eq!(resolve("", "page.html#top"), "/page.html");
+        assert_eq!(resolve("/a/b/", "./"), "/a/b/");
+    }
+
+    #[test]
+    fn test_render() {
+        let html = "<html><head><title>My Page</title><style>p { color: red }</style></head>
+<body>
+<h2>Links</h2>
+<p>Visit <a href=\"gopher://sdf.org/1/maps\">SDF</a> or
+<a href=\"https://example.com\">the web</a>, or read <a href=\"notes.txt\">notes</a>.</p>
+<ul><li>one</li><li>two <a href=\"#top\">top</a></li></ul>
+<ol><li>first</li><li>second</li></ol>
+<pre>  keep
+    this</pre>
+</body></html>";
+        let map = to_gophermap("gopher://example.org/h/site/index.html", html);
+        let menu = menu::parse("test", map.clone());
+        let texts: Vec<&str> = menu.lines.iter().map(|l| l.text(&menu.raw)).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "My Page",
+                "=======",
+                "",
+                "Links",
+                "-----",
+                "",
+                "Visit SDF [1] or the web [2], or read notes [3].",
+                "SDF",
+                "the web",
+                "notes",
+                "",
+                "* one",
+                "* two top",
+                "",
+                "1. first",
+                "2. second",
+                "",
+                "  keep",
+                "    this",
+            ]
+        );
+        assert_eq!(menu.links.len(), 3);
+        let url = |i: usize| menu.lines[menu.links[i]].url(&menu.raw);
+        assert_eq!(url(0), "gopher://sdf.org/1/maps");
+        assert_eq!(url(1), "https://example.com");
+        assert_eq!(url(2), "gopher://example.org/0/site/notes.txt");
+
+        let map = to_gophermap(
+            "file:///srv/site/index.html",
+            "<a href=../notes.txt>notes</a>",
+        );
+        let menu = menu::parse("test", map);
+        assert_eq!(menu.lines[1].url(&menu.raw), "file:///srv/notes.txt");
+    }
+
+    #[test]
+    fn test_wrap() {
+        let text = "one two three four five";
+        assert_eq!(wrap(text, 9), vec!["one two", "three", "four five"]);
+        assert_eq!(wrap("", 9), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_links() {
+        let html = "<DL><p>\n<DT><A HREF=\"gopher://sdf.org/\" ADD_DATE=\"1\">SDF\n  &amp; <b>friends</b></A>\n\
+                    <a name=top>anchor</a><LI><a href='gopher://bitreich.org/1/lawn'>Lawn</a>";
+        assert_eq!(
+            links(html),
+            vec![
+                ("SDF & friends".to_string(), "gopher://sdf.org/".to_string()),
+                (
+                    "Lawn".to_string(),
+                    "gopher://bitreich.org/1/lawn".to_string()
+                ),
+            ]
+        );
+    }
+}
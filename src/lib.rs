@@ -38,13 +38,27 @@ pub mod utils;
 #[macro_use]
 pub mod color;
 pub mod args;
+pub mod batch;
 pub mod bookmarks;
+pub mod cache;
+pub mod caps;
 pub mod config;
+pub mod export;
+pub mod finder;
 pub mod gopher;
+pub mod gophermap;
 pub mod help;
 pub mod history;
+pub mod html;
+pub mod linkcheck;
+pub mod lint;
 pub mod menu;
+pub mod mirror;
 pub mod phetchdir;
+pub mod prefetch;
+pub mod preview;
+pub mod server;
+pub mod session;
 pub mod terminal;
 pub mod text;
 pub mod ui;
@@ -0,0 +1,293 @@
+//! The link checker probes every link in a menu to find the dead
+//! ones, for `phetch --check-links` and the `c` key.
+//!
+//! A probe connects to the link's server, sends its selector, and
+//! waits for the first bytes of a response. Probes run in parallel,
+//! so checking a big menu takes about as long as its slowest server.
+
+use crate::{gopher, menu};
+use std::{
+    fmt,
+    io::{self, Read},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+/// How many probes to run at once.
+pub const WORKERS: usize = 8;
+
+/// What happened when we probed a link.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Status {
+    /// Server responded.
+    Ok,
+    /// Server didn't connect or respond in time.
+    Timeout,
+    /// Server refused the connection.
+    Refused,
+    /// Hostname didn't resolve.
+    DnsFailure,
+    /// Something else went wrong.
+    Failed(String),
+    /// Not a Gopher or Telnet link, so not checked.
+    Skipped,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Timeout => write!(f, "timeout"),
+            Status::Refused => write!(f, "refused"),
+            Status::DnsFailure => write!(f, "DNS failure"),
+            Status::Failed(_) => write!(f, "failed"),
+            Status::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// A link found in a menu, and what happened when we probed it.
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// The link's line in the original Gophermap.
+    pub line: String,
+    /// The link's URL.
+    pub url: String,
+    /// Result of the probe. Skipped until checked.
+    pub status: Status,
+}
+
+/// Find all the links in a raw Gophermap.
+pub fn links(raw: &str) -> Vec<Check> {
+    let mut links = vec![];
+    for line in raw.split_terminator('\n') {
+        let line = line.trim_end_matches('\r');
+        if line == "." {
+            break;
+        }
+        match menu::parse_line(0, line) {
+            Some(l) if l.typ.is_link() => links.push(Check {
+                line: line.to_string(),
+                url: l.url(line),
+                status: Status::Skipped,
+            }),
+            _ => {}
+        }
+    }
+    links
+}
+
+/// Probe all the links, `WORKERS` at a time. Returns them in the
+/// same order.
+pub fn check(links: Vec<Check>, tls: bool, tor: bool) -> Vec<Check> {
+    let total = links.len();
+    let queue = Arc::new(Mutex::new(
+        links.into_iter().enumerate().collect::<Vec<_>>(),
+    ));
+    let (tx, rx) = mpsc::channel();
+
+    for _ in 0..WORKERS.min(total) {
+        let queue = queue.clone();
+        let tx = tx.clone();
+        thread::spawn(move || loop {
+            let next = queue.lock().ok().and_then(|mut q| q.pop());
+            let (i, mut link) = match next {
+                Some(next) => next,
+                None => return,
+            };
+            link.status = probe(&link.url, tls, tor);
+            if tx.send((i, link)).is_err() {
+                return;
+            }
+        });
+    }
+    drop(tx);
+
+    let mut checked = rx.iter().collect::<Vec<_>>();
+    checked.sort_by_key(|(i, _)| *i);
+    checked.into_iter().map(|(_, link)| link).collect()
+}
+
+/// Probe a single URL, connecting the same way `gopher::fetch_url()`
+/// does, over TLS or Tor if they're on.
+pub fn probe(url: &str, tls: bool, tor: bool) -> Status {
+    let telnet = url.starts_with("telnet://");
+    if url.contains("://") && !url.starts_with("gopher://") && !telnet {
+        return Status::Skipped;
+    }
+
+    // Tor looks up hosts itself
+    let u = gopher::parse_url(url);
+    if !tor {
+        let addr = match format!("{}:{}", u.host, u.port)
+            .to_socket_addrs()
+            .map(|mut addrs| addrs.next())
+        {
+            Ok(Some(addr)) => addr,
+            Ok(None) | Err(_) => return Status::DnsFailure,
+        };
+        // telnet servers don't want a selector, just see if one's there
+        if telnet {
+            return match TcpStream::connect_timeout(&addr, gopher::TCP_TIMEOUT_DURATION) {
+                Ok(_) => Status::Ok,
+                Err(e) => status_for(&e),
+            };
+        }
+    }
+
+    gopher::request(u.host, u.port, u.sel, tls, tor)
+        .and_then(|mut stream| {
+            if telnet {
+                Ok(Status::Ok)
+            } else {
+                read_some(&mut stream)
+            }
+        })
+        .unwrap_or_else(|e| status_for(&e))
+}
+
+/// Wait for the first bytes of a response.
+fn read_some(stream: &mut impl Read) -> io::Result<Status> {
+    let mut buf = [0; 64];
+    match stream.read(&mut buf)? {
+        0 => Ok(Status::Failed("empty response".into())),
+        _ => Ok(Status::Ok),
+    }
+}
+
+/// Status for a connection error.
+fn status_for(e: &io::Error) -> Status {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Status::Timeout,
+        io::ErrorKind::ConnectionRefused => Status::Refused,
+        _ => Status::Failed(e.to_string()),
+    }
+}
+
+/// One-line summary of a check, ex: "3 links: 2 ok, 1 refused"
+pub fn summary(checks: &[Check]) -> String {
+    let mut out = format!(
+        "{} link{}",
+        checks.len(),
+        if checks.len() == 1 { "" } else { "s" }
+    );
+    let mut sep = ": ";
+    for status in &order() {
+        let count = checks.iter().filter(|c| same(&c.status, status)).count();
+        if count > 0 {
+            out.push_str(&format!("{}{} {}", sep, count, status));
+            sep = ", ";
+        }
+    }
+    out
+}
+
+/// Gophermap report of a check, grouping links by status. Problems
+/// come first.
+pub fn report(url: &str, checks: &[Check]) -> String {
+    let mut out = format!("iLink check for {}\r\ni\r\ni{}\r\n", url, summary(checks));
+    for status in &order() {
+        let group = checks
+            .iter()
+            .filter(|c| same(&c.status, status))
+            .collect::<Vec<_>>();
+        if group.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("i\r\ni** {} ({}) **\r\n", status, group.len()));
+        for check in group {
+            match (&check.status, check.line.find('\t')) {
+                (Status::Failed(msg), Some(i)) => out.push_str(&format!(
+                    "{} ({}){}\r\n",
+                    &check.line[..i],
+                    msg,
+                    &check.line[i..]
+                )),
+                _ => out.push_str(&format!("{}\r\n", check.line)),
+            }
+        }
+    }
+    out
+}
+
+/// Order of the groups in a report.
+fn order() -> Vec<Status> {
+    vec![
+        Status::DnsFailure,
+        Status::Refused,
+        Status::Timeout,
+        Status::Failed(String::new()),
+        Status::Ok,
+        Status::Skipped,
+    ]
+}
+
+/// Same kind of Status, ignoring any error message.
+fn same(a: &Status, b: &Status) -> bool {
+    match (a, b) {
+        (Status::Failed(_), Status::Failed(_)) => true,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn test_links() {
+        let links = links(
+            "iWelcome!\t\terror.host\t1\r\n1Phlog\t/phlog\tsdf.org\t70\r\n\
+             hWeb\tURL:https://example.com\t\t70\r\n.\r\n0After\t/x\tsdf.org\t70\r\n",
+        );
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].url, "gopher://sdf.org/1/phlog");
+        assert_eq!(links[1].url, "https://example.com");
+    }
+
+    #[test]
+    fn test_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                stream.write_all(b"iHello\r\n").unwrap();
+            }
+        });
+        let url = format!("gopher://127.0.0.1:{}/1/", port);
+        assert_eq!(probe(&url, false, false), Status::Ok);
+
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let url = format!("gopher://127.0.0.1:{}/1/", port);
+        assert_eq!(probe(&url, false, false), Status::Refused);
+
+        assert_eq!(probe("https://example.com", false, false), Status::Skipped);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut checks =
+            links("1Up\t/\tup.org\t70\r\n1Down\t/\tdown.org\t70\r\n0Odd\t/x\todd.org\t70\r\n");
+        checks[0].status = Status::Ok;
+        checks[1].status = Status::Refused;
+        checks[2].status = Status::Failed("empty response".into());
+        assert_eq!(summary(&checks), "3 links: 1 refused, 1 failed, 1 ok");
+        assert_eq!(
+            report("gopher://test", &checks),
+            "iLink check for gopher://test\r\ni\r\ni3 links: 1 refused, 1 failed, 1 ok\r
+i\r\ni** refused (1) **\r\n1Down\t/\tdown.org\t70\r
+i\r\ni** failed (1) **\r\n0Odd (empty response)\t/x\todd.org\t70\r
+i\r\ni** ok (1) **\r\n1Up\t/\tup.org\t70\r\n"
+        );
+        assert_eq!(summary(&[]), "0 links");
+    }
+}
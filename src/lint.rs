@@ -0,0 +1,245 @@
+//! `phetch --lint` checks a Gophermap for problems that `menu::parse`
+//! would quietly tolerate or drop: unknown item types, missing
+//! fields, bad ports, and lines too long to display.
+
+use crate::{gopher::Type, ui::MAX_COLS};
+use std::fmt;
+
+/// How bad a Problem is. Errors break links or drop lines, warnings
+/// are merely untidy.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Severity {
+    /// Displays or works, but not quite right.
+    Warning,
+    /// Link won't work or line won't be shown.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found in a Gophermap.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Warning or Error?
+    pub severity: Severity,
+    /// What's wrong.
+    pub msg: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.severity, self.msg)
+    }
+}
+
+/// Check a raw Gophermap and return all the problems found, in line
+/// order.
+///
+/// If `source` is true, the Gophermap is checked the way a server
+/// reads one from disk: lines without tabs are info text, and links
+/// may leave out their host and port for the server to fill in, and
+/// lines may end with LF instead of CRLF.
+pub fn lint(raw: &str, source: bool) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut warned_crlf = false;
+
+    for (i, line) in raw.split_terminator('\n').enumerate() {
+        let linenum = i + 1;
+        let mut problem = |severity, msg: String| {
+            problems.push(Problem {
+                line: linenum,
+                severity,
+                msg,
+            })
+        };
+
+        // servers add the CR to lines they read from disk
+        if !source && !line.ends_with('\r') && !warned_crlf {
+            warned_crlf = true;
+            problem(
+                Severity::Warning,
+                "line ends with LF instead of CRLF (further lines not reported)".into(),
+            );
+        }
+        let line = line.trim_end_matches('\r');
+
+        // end of response
+        if line == "." {
+            break;
+        }
+        // source Gophermaps turn them into blank info lines
+        if line.is_empty() {
+            if !source {
+                problem(Severity::Warning, "empty line will be skipped".into());
+            }
+            continue;
+        }
+        if source && !line.contains('\t') {
+            if line.chars().count() > MAX_COLS {
+                problem(Severity::Warning, too_long(line));
+            }
+            continue;
+        }
+
+        let c = line.chars().next().unwrap_or(' ');
+        let typ = match Type::from(c) {
+            Some(t) => t,
+            None => {
+                problem(
+                    Severity::Error,
+                    format!("unknown item type {:?}, line will be skipped", c),
+                );
+                continue;
+            }
+        };
+        if !typ.is_supported() {
+            problem(
+                Severity::Warning,
+                format!("{:?} items aren't supported", typ),
+            );
+        }
+
+        let fields: Vec<&str> = line[c.len_utf8()..].split('\t').collect();
+        let text = fields[0];
+        if text.chars().count() > MAX_COLS {
+            problem(Severity::Warning, too_long(text));
+        }
+
+        if typ.is_info() {
+            if fields.len() < 4 && !source {
+                problem(
+                    Severity::Warning,
+                    format!("info line has {} of 4 fields", fields.len()),
+                );
+            }
+            continue;
+        }
+
+        if fields.len() == 1 {
+            problem(Severity::Error, "link has no tab-separated fields".into());
+            continue;
+        }
+        if fields.len() < 4 && !source {
+            problem(
+                Severity::Error,
+                format!(
+                    "link has {} of 4 fields, missing host or port",
+                    fields.len()
+                ),
+            );
+        }
+
+        let sel = fields[1];
+        if sel.starts_with("URL:") || sel.starts_with("/URL:") {
+            continue;
+        }
+        if let Some(host) = fields.get(2) {
+            if host.is_empty() && !source {
+                problem(Severity::Error, "link has an empty host".into());
+            }
+        }
+        if let Some(port) = fields.get(3) {
+            match port.parse::<u16>() {
+                Ok(p) if p > 0 => {}
+                _ => problem(Severity::Error, format!("bad port: {:?}", port)),
+            }
+        }
+    }
+
+    problems
+}
+
+/// Warning message for text that won't fit on screen.
+fn too_long(text: &str) -> String {
+    format!(
+        "text is {} columns long, will be truncated to {}",
+        text.chars().count(),
+        MAX_COLS
+    )
+}
+
+/// Are any of these problems errors?
+pub fn has_errors(problems: &[Problem]) -> bool {
+    problems.iter().any(|p| p.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean() {
+        let raw = "iWelcome!\t\terror.host\t1\r\n1Phlog\t/phlog\tsdf.org\t70\r\n\
+                   hWeb\tURL:https://example.com\t\t\r\n.\r\nthis is ignored\n";
+        assert_eq!(lint(raw, false), vec![]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let raw = "iWelcome!\r
+1Phlog\r
+0Notes\t/notes.txt\tsdf.org\r
+0Notes\t/notes.txt\tsdf.org\tseventy\r
+9File\t/file\t\t70\r
+\r
+Xbad\t/x\tsdf.org\t70\r
+TMainframe\t/\tibm.com\t23\r
+";
+        let problems: Vec<String> = lint(raw, false).iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "line 1: warning: info line has 1 of 4 fields",
+                "line 2: error: link has no tab-separated fields",
+                "line 3: error: link has 3 of 4 fields, missing host or port",
+                "line 4: error: bad port: \"seventy\"",
+                "line 5: error: link has an empty host",
+                "line 6: warning: empty line will be skipped",
+                "line 7: error: unknown item type 'X', line will be skipped",
+                "line 8: warning: Telnet3270 items aren't supported",
+            ]
+        );
+        assert!(has_errors(&lint(raw, false)));
+    }
+
+    #[test]
+    fn test_source() {
+        let raw = "Welcome!\r\n1Phlog\tphlog\r\n0Notes\t/notes.txt\t\tgopher\r\n";
+        let problems: Vec<String> = lint(raw, true).iter().map(|p| p.to_string()).collect();
+        assert_eq!(problems, vec!["line 3: error: bad port: \"gopher\""]);
+
+        let unix = "Welcome!\n1Phlog\tphlog\n";
+        assert!(lint(unix, true).is_empty());
+
+        let blank = "Welcome!\n\n1Phlog\tphlog\n";
+        assert!(lint(blank, true).is_empty());
+        let problems = lint(blank, false);
+        assert!(problems
+            .iter()
+            .any(|p| p.msg == "empty line will be skipped"));
+    }
+
+    #[test]
+    fn test_long_lines_and_lf() {
+        let raw = format!("i{}\t\terror.host\t1\n1ok\t/\thost\t70\n", "x".repeat(80));
+        let problems = lint(&raw, false);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line, 1);
+        assert_eq!(problems[0].severity, Severity::Warning);
+        assert!(problems[0].msg.starts_with("line ends with LF"));
+        assert_eq!(
+            problems[1].msg,
+            "text is 80 columns long, will be truncated to 77"
+        );
+        assert!(!has_errors(&problems));
+    }
+}
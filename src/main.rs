@@ -1,8 +1,11 @@
 use phetch::{
-    args, gopher, menu,
+    args, batch,
+    bookmarks::{self, Bookmarks},
+    caps, export, gopher, gophermap, html, linkcheck, lint, mirror, server,
     ui::{Mode, UI},
+    utils,
 };
-use std::{env, process};
+use std::{env, fs, io, path::Path, process};
 
 fn main() {
     process::exit(run())
@@ -19,12 +22,51 @@ fn run() -> i32 {
         }
     };
 
+    // structured output for --print and --raw
+    if let Some(format) = cfg.format {
+        let as_is = cfg.mode == Mode::Raw;
+        return print_formatted(&cfg.start, format, as_is, cfg.tls, cfg.tor);
+    }
+
     // check for simple modes
     match cfg.mode {
         Mode::Raw => return print_raw(&cfg.start, cfg.tls, cfg.tor),
         Mode::Version => return print_version(),
         Mode::Help => return print_usage(),
-        Mode::NoTTY => return print_plain(&cfg.start, cfg.tls, cfg.tor),
+        Mode::Lint => return lint(&cfg.start, cfg.tls, cfg.tor),
+        Mode::Download => return download(&cfg.start, cfg.output.as_ref(), cfg.tls, cfg.tor),
+        Mode::Bookmarks => match cfg.bookmarks {
+            Some(cmd) => return bookmarks(cmd),
+            None => return 1,
+        },
+        Mode::Batch => {
+            let opts = batch::Options {
+                jobs: cfg.jobs,
+                output: cfg.output.as_ref().filter(|o| *o != "-").map(|o| o.into()),
+                tls: cfg.tls,
+                tor: cfg.tor,
+            };
+            return batch(&cfg.start, &opts);
+        }
+        Mode::CheckLinks => return check_links(&cfg.start, cfg.tls, cfg.tor),
+        Mode::Mirror => {
+            let output = cfg
+                .output
+                .clone()
+                .unwrap_or_else(|| mirror::default_output(&cfg.start));
+            let opts = mirror::Options {
+                depth: cfg.depth,
+                prefix: cfg.prefix,
+                output: output.into(),
+                tls: cfg.tls,
+                tor: cfg.tor,
+            };
+            return mirror(&cfg.start, &opts);
+        }
+        Mode::Serve => return serve(&cfg.start, cfg.port),
+        Mode::NoTTY => {
+            return print_formatted(&cfg.start, export::Format::Plain, false, cfg.tls, cfg.tor)
+        }
         Mode::Print => cfg.wide = true,
         Mode::Run => {}
     }
@@ -32,10 +74,26 @@ fn run() -> i32 {
     // load url
     let start = cfg.start.clone();
     let mode = cfg.mode;
+    let restore = cfg.restore && mode == Mode::Run;
+    let start_given = cfg.start_given;
     let mut ui = UI::new(cfg);
-    if let Err(e) = ui.open(&start, &start) {
-        eprintln!("{}", e);
-        return 1;
+
+    // reopen last session's pages
+    let mut restored = false;
+    if restore {
+        match ui.restore() {
+            Ok(r) => restored = r,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+    if !restored || start_given {
+        if let Err(e) = ui.open(&start, &start) {
+            eprintln!("{}", e);
+            return 1;
+        }
     }
 
     // print rendered version
@@ -81,20 +139,55 @@ Usage:
 
     phetch [options]       Launch phetch in interactive mode
     phetch [options] url   Open Gopher URL in interactive mode
+    phetch bookmarks CMD   Manage bookmarks from the command line
+
+Bookmarks:
+
+    list                   Show bookmarks, numbered
+    add URL [LABEL]        Add a bookmark
+    rm N                   Remove bookmark N
+    mv N M                 Move bookmark N to position M
+    export [FORMAT]        Print all bookmarks as a Gophermap or FORMAT
+    import FILE|- [FORMAT] Add bookmarks from another client's file
+
+    Formats: gophermap, vf1, lynx, netscape, urls
 
 Options:
 
     -s, --tls              Try to open Gopher URLs securely w/ TLS
     -o, --tor              Use local Tor proxy to open all pages
     -S, -O                 Disable TLS or Tor
-                              
+    -P, --private          Don't save history or session
+    --restore              Reopen the pages you had open last time
+
     -r, --raw              Print raw Gopher response only
     -p, --print            Print rendered Gopher response only
     -l, --local            Connect to 127.0.0.1:7070
+    --format FORMAT        Print as json, tsv, markdown, html, dump, or plain
+    --dump URL             Print URL with a numbered list of its links
+
+    --lint URL|FILE        Check a Gophermap for problems
+
+    --download URL         Save URL's raw bytes without converting them
+                           With --output PATH, save to PATH or - for stdout
+
+    --batch [FILE]         Fetch URLs listed in FILE or stdin, one per line
+    --jobs N               Fetch N URLs at once with --batch (default: 4)
+                           With --output DIR, save each one in DIR
+
+    --check-links URL|FILE Check a menu or bookmarks.gph for dead links
+
+    --mirror URL           Save the gopherhole at URL to disk
+    --depth N              Follow links N menus deep (default: 3)
+    --prefix               Only save selectors under URL's
+    --output DIR           Save to DIR (default: the host name)
+
+    --serve DIR            Serve DIR over Gopher at 127.0.0.1:7070
+    --port N               Use port N with --serve
 
     -c, --config FILE      Use instead of ~/.config/phetch/phetch.conf
-    -C, --no-config        Don't use any config file            
-    
+    -C, --no-config        Don't use any config file
+
     -h, --help             Show this screen
     -v, --version          Show phetch version
 
@@ -105,9 +198,281 @@ Once you've launched phetch, use `ctrl-h` to view the on-line help."
     0
 }
 
+/// --lint
+/// Local files are checked as Gophermap sources, URLs as they're
+/// served. Exits nonzero if any errors are found.
+fn lint(target: &str, tls: bool, tor: bool) -> i32 {
+    let path = gophermap::file_path(target);
+    let raw = if path.is_file() {
+        fs::read(&path).map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+    } else {
+        gopher::fetch_url(target, tls, tor).map(|(_, res)| res)
+    };
+    let raw = match raw {
+        Ok(raw) => raw,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let problems = lint::lint(&raw, path.is_file());
+    for p in &problems {
+        println!("{}:{}: {}: {}", target, p.line, p.severity, p.msg);
+    }
+    let errors = problems
+        .iter()
+        .filter(|p| p.severity == lint::Severity::Error)
+        .count();
+    eprintln!(
+        "{}: {} errors, {} warnings",
+        target,
+        errors,
+        problems.len() - errors
+    );
+    if lint::has_errors(&problems) {
+        1
+    } else {
+        0
+    }
+}
+
+/// --download
+/// Streams raw bytes to a file or stdout, with progress on stderr if
+/// it's a terminal.
+fn download(url: &str, output: Option<&String>, tls: bool, tor: bool) -> i32 {
+    let tty = atty::is(atty::Stream::Stderr);
+    let mut progress = |bytes| {
+        if tty {
+            eprint!("\r-> {} {}\x1b[K", url, utils::human_bytes(bytes));
+        }
+    };
+
+    let path = match output {
+        Some(path) => path.to_string(),
+        None => match gopher::download_filename(url) {
+            Ok(name) if !name.is_empty() => name,
+            _ => {
+                eprintln!("Can't pick a filename for {}, use -o PATH", url);
+                return 1;
+            }
+        },
+    };
+
+    let res = if path == "-" {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        gopher::copy_url(url, &mut out, tls, tor, &mut progress)
+    } else {
+        fs::File::create(&path).and_then(|mut file| {
+            let res = gopher::copy_url(url, &mut file, tls, tor, &mut progress);
+            if res.is_err() {
+                fs::remove_file(&path).ok();
+            }
+            res
+        })
+    };
+    if tty {
+        eprintln!();
+    }
+
+    match res {
+        Ok(bytes) => {
+            if path != "-" {
+                eprintln!("-> Saved {} to {}", utils::human_bytes(bytes), path);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// phetch bookmarks
+/// Lists print tab separated so they're easy to use in scripts.
+fn bookmarks(cmd: bookmarks::Command) -> i32 {
+    use bookmarks::Command;
+    let res = Bookmarks::load().and_then(|mut marks| match cmd {
+        Command::List => {
+            for (i, b) in marks.list().iter().enumerate() {
+                println!("{}\t{}\t{}", i + 1, b.url, b.label);
+            }
+            Ok(())
+        }
+        Command::Export(format) => {
+            print!("{}", bookmarks::export(format)?);
+            Ok(())
+        }
+        Command::Add(url, label) => {
+            let label = label.unwrap_or_else(|| bookmarks::default_label(&url));
+            marks.add(&label, &url);
+            marks.save()?;
+            eprintln!("Added #{}: {}", marks.len(), label);
+            Ok(())
+        }
+        Command::Remove(n) => {
+            let b = marks.remove(n)?;
+            marks.save()?;
+            eprintln!("Removed #{}: {} ({})", n, b.label, b.url);
+            Ok(())
+        }
+        Command::Move(from, to) => {
+            marks.move_to(from, to)?;
+            marks.save()?;
+            eprintln!("Moved #{} to #{}", from, to);
+            Ok(())
+        }
+        Command::Import(file, format) => {
+            let raw = if file == "-" {
+                let mut raw = String::new();
+                io::Read::read_to_string(&mut io::stdin(), &mut raw).map(|_| raw)
+            } else {
+                fs::read(&file).map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+            };
+            let added = bookmarks::import(&raw?, format)?;
+            eprintln!(
+                "Imported {} bookmark{}",
+                added,
+                if added == 1 { "" } else { "s" }
+            );
+            Ok(())
+        }
+    });
+
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// --batch
+/// Fetches every URL in the list, then prints how each one went on
+/// stderr. Exits nonzero if any failed.
+fn batch(list: &str, opts: &batch::Options) -> i32 {
+    let urls = if list == "-" {
+        let stdin = io::stdin();
+        let input = stdin.lock();
+        batch::read_urls(input)
+    } else {
+        fs::File::open(list).and_then(|file| batch::read_urls(io::BufReader::new(file)))
+    };
+    let urls = match urls {
+        Ok(urls) => urls,
+        Err(e) => {
+            eprintln!("{}: {}", list, e);
+            return 1;
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match batch::run(urls, opts, &mut out) {
+        Ok(outcomes) => {
+            eprint!("{}", batch::summary(&outcomes));
+            if outcomes.iter().any(|o| o.result.is_err()) {
+                1
+            } else {
+                0
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// --check-links
+/// Prints the status of every link in the menu. Exits nonzero if any
+/// are dead.
+fn check_links(target: &str, tls: bool, tor: bool) -> i32 {
+    let path = Path::new(target);
+    let url = if path.is_file() {
+        match fs::canonicalize(path) {
+            Ok(path) => gophermap::file_url(&path),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    } else {
+        target.to_string()
+    };
+
+    let raw = match fetch(&url, tls, tor) {
+        Ok((gopher::Type::Menu, raw)) => raw,
+        Ok((typ, _)) => {
+            eprintln!("Can only check links in menus, not {:?}", typ);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let checks = linkcheck::check(linkcheck::links(&raw), tls, tor);
+    let mut dead = 0;
+    for check in &checks {
+        let status = match &check.status {
+            linkcheck::Status::Failed(msg) => format!("failed ({})", msg),
+            status => status.to_string(),
+        };
+        match check.status {
+            linkcheck::Status::Ok | linkcheck::Status::Skipped => {}
+            _ => dead += 1,
+        }
+        println!("{:<12} {}", status, check.url);
+    }
+    eprintln!("{}: {}", target, linkcheck::summary(&checks));
+    if dead > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// --mirror
+fn mirror(url: &str, opts: &mirror::Options) -> i32 {
+    match mirror::run(url, opts) {
+        Ok(stats) => {
+            eprintln!(
+                "-> Saved to {}: {} fetched, {} already saved, {} failed",
+                opts.output.to_string_lossy(),
+                stats.fetched,
+                stats.skipped,
+                stats.failed
+            );
+            if stats.failed > 0 {
+                1
+            } else {
+                0
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// --serve
+fn serve(dir: &str, port: u16) -> i32 {
+    if let Err(e) = server::run(dir, port) {
+        eprintln!("{}", e);
+        return 1;
+    }
+    0
+}
+
 /// Print just the raw Gopher response.
 fn print_raw(url: &str, tls: bool, tor: bool) -> i32 {
-    match gopher::fetch_url(url, tls, tor) {
+    match fetch(url, tls, tor) {
         Ok((_, response)) => {
             println!("{}", response);
             0
@@ -119,31 +484,48 @@ fn print_raw(url: &str, tls: bool, tor: bool) -> i32 {
     }
 }
 
-/// Print a colorless, plain version of the response for a non-tty
-/// (like a pipe).
-fn print_plain(url: &str, tls: bool, tor: bool) -> i32 {
-    let mut out = String::new();
-    let typ = gopher::type_for_url(url);
-    match gopher::fetch_url(url, tls, tor) {
-        Ok((_, response)) => match typ {
-            gopher::Type::Menu => {
-                let menu = menu::parse(url, response);
-                for line in menu.lines {
-                    out.push_str(line.text(&menu.raw));
-                    out.push('\n');
-                }
-            }
-            gopher::Type::Text => println!("{}", response.trim_end_matches(".\r\n")),
-            _ => {
-                eprintln!("can't print gopher type: {:?}", typ);
-                return 1;
-            }
-        },
+/// Print a page in a structured format, for --format, or colorless
+/// and plain for a non-tty (like a pipe). With `as_is` (--raw), HTML
+/// isn't converted first and plain is the response unchanged.
+fn print_formatted(url: &str, format: export::Format, as_is: bool, tls: bool, tor: bool) -> i32 {
+    let (typ, response) = match fetch(url, tls, tor) {
+        Ok(res) => res,
         Err(e) => {
             eprintln!("{}", e);
             return 1;
         }
+    };
+    if as_is && format == export::Format::Plain {
+        println!("{}", response);
+        return 0;
     }
-    print!("{}", out);
+    let (raw, is_menu) = match typ {
+        gopher::Type::Menu | gopher::Type::Search => (response, true),
+        gopher::Type::HTML if !as_is => (html::to_gophermap(url, &response), true),
+        gopher::Type::Text | gopher::Type::HTML => (response, false),
+        _ => {
+            eprintln!("can't print gopher type: {:?}", typ);
+            return 1;
+        }
+    };
+    print!("{}", export::render(url, &raw, is_menu, format));
     0
 }
+
+/// Fetch a Gopher URL or read a local file:// URL. Returns the
+/// Gopher type of the response and the response itself.
+fn fetch(url: &str, tls: bool, tor: bool) -> io::Result<(gopher::Type, String)> {
+    if url.starts_with("file://") {
+        gophermap::load_url(url)
+    } else {
+        let (_, body) = gopher::fetch_url_bytes(url, tls, tor)?;
+        let charset = if std::str::from_utf8(&body).is_ok() {
+            None
+        } else {
+            let u = gopher::parse_url(url);
+            caps::fetch(u.host, u.port, tls, tor).and_then(|c| c.charset().map(String::from))
+        };
+        let res = gopher::decode(&body, charset.as_deref());
+        Ok((gopher::type_for_url(url), res))
+    }
+}
@@ -91,16 +91,17 @@ impl Line {
         }
     }
 
-    /// Get the URL for this line, if it's a link.
-    pub fn url(&self, raw: &str) -> String {
-        if !self.typ.is_link() || self.text_end >= self.end {
-            return String::from("");
-        }
-
-        let line = &raw[self.text_end..self.end].trim_end_matches('\r');
+    /// Returns the (selector, host, port) fields of this line, given
+    /// a raw Gopher response. Missing fields get default values.
+    pub fn fields<'a>(&self, raw: &'a str) -> (&'a str, &'a str, &'a str) {
         let mut sel = "(null)";
         let mut host = "localhost";
         let mut port = "70";
+        if self.text_end >= self.end {
+            return (sel, host, port);
+        }
+
+        let line = raw[self.text_end..self.end].trim_end_matches('\r');
         for (i, chunk) in line.split('\t').enumerate() {
             match i {
                 0 => {}
@@ -110,23 +111,22 @@ impl Line {
                 _ => break,
             }
         }
+        (sel, host, port)
+    }
 
-        if self.typ.is_html() {
+    /// Get the URL for this line, if it's a link.
+    pub fn url(&self, raw: &str) -> String {
+        if !self.typ.is_link() || self.text_end >= self.end {
+            return String::from("");
+        }
+
+        let (sel, host, port) = self.fields(raw);
+        if sel.starts_with("URL:") || sel.starts_with("/URL:") {
             sel.trim_start_matches('/')
                 .trim_start_matches("URL:")
                 .to_string()
-        } else if self.typ.is_telnet() {
-            format!("telnet://{}:{}", host, port)
         } else {
-            let mut path = format!("/{}{}", self.typ, sel);
-            if sel.is_empty() || sel == "/" {
-                path.clear();
-            }
-            if port == "70" {
-                format!("gopher://{}{}", host, path)
-            } else {
-                format!("gopher://{}:{}{}", host, port, path)
-            }
+            gopher::Url::new(self.typ, host, port, sel).to_string()
         }
     }
 }
@@ -154,6 +154,10 @@ impl View for Menu {
         self.tor
     }
 
+    fn is_loaded(&self) -> bool {
+        true
+    }
+
     fn raw(&self) -> &str {
         self.raw.as_ref()
     }
@@ -178,6 +182,33 @@ impl View for Menu {
         self.size = (cols, rows);
     }
 
+    fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    fn set_scroll(&mut self, scroll: usize) {
+        self.scroll = scroll.min(self.lines.len().saturating_sub(1));
+    }
+
+    fn selected_link(&self) -> Option<usize> {
+        if self.links.is_empty() {
+            None
+        } else {
+            Some(self.link)
+        }
+    }
+
+    fn set_selected_link(&mut self, link: usize) {
+        if link < self.links.len() {
+            self.link = link;
+            self.scroll_to(link);
+        }
+    }
+
+    fn selected_url(&self) -> Option<String> {
+        self.link(self.link).map(|line| line.url(&self.raw))
+    }
+
     fn url(&self) -> &str {
         self.url.as_ref()
     }
@@ -769,6 +800,10 @@ impl Menu {
 
         match key {
             Key::Char('\n') => self.action_open(),
+            Key::Char('t') if !self.links.is_empty() => match self.action_open() {
+                Action::Open(title, url) => Action::OpenTab(title, url),
+                action => action,
+            },
             Key::Up | Key::Ctrl('p') | Key::Char('p') | Key::Ctrl('k') | Key::Char('k') => {
                 self.action_up()
             }
@@ -964,6 +999,18 @@ i---------------------------------------------------------
         assert_eq!(menu.lines[8].text(&menu.raw), "-----------");
     }
 
+    #[test]
+    fn test_html_selector() {
+        let menu = parse!(
+            "hMy Site\t/site/index.html\texample.org\t70\r\nhWeb\tURL:https://example.com\t\t"
+        );
+        assert_eq!(
+            menu.lines[0].url(&menu.raw),
+            "gopher://example.org/h/site/index.html"
+        );
+        assert_eq!(menu.lines[1].url(&menu.raw), "https://example.com");
+    }
+
     #[test]
     fn test_no_path() {
         let menu = parse!("1Circumlunar Space		circumlunar.space	70");
@@ -0,0 +1,379 @@
+//! `phetch --mirror` crawls a gopherhole and saves it to disk, for
+//! archiving servers before they disappear.
+//!
+//! Each menu is saved as a `gophermap` in a directory named after its
+//! selector, with links to mirrored items rewritten as relative
+//! selectors. That means the mirror can be browsed with
+//! `phetch file://DIR` or served again with `phetch --serve DIR`.
+//! If a file and a directory would need the same name, like for a
+//! text file at `/x` and a menu at `/x/`, whichever is found second
+//! gets a `_1` (or `_2`, ...) on the end of its name.
+//!
+//! Items are downloaded to a `.part` file and renamed when complete,
+//! so re-running an interrupted mirror skips what's already on disk.
+//! Menus are always re-fetched, since they're how we find everything
+//! else.
+
+use crate::{
+    gopher::{self, Type},
+    gophermap::GOPHERMAP,
+    menu,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, Result},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// How long to wait between requests, to be polite.
+pub const DELAY: Duration = Duration::from_millis(500);
+
+/// Default number of menus deep to crawl.
+pub const DEFAULT_DEPTH: usize = 3;
+
+/// What a mirror should fetch, and where to save it.
+pub struct Options {
+    /// How many menus deep to go from the starting URL.
+    pub depth: usize,
+    /// Only follow links whose selectors start with the starting
+    /// URL's selector.
+    pub prefix: bool,
+    /// Directory to save the mirror in.
+    pub output: PathBuf,
+    /// Use TLS?
+    pub tls: bool,
+    /// Use Tor?
+    pub tor: bool,
+}
+
+/// What happened during a mirror.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Menus and items downloaded.
+    pub fetched: usize,
+    /// Items already on disk from a previous run.
+    pub skipped: usize,
+    /// Items we couldn't fetch or save.
+    pub failed: usize,
+}
+
+/// Mirror the gopherhole at `url`.
+pub fn run(url: &str, opts: &Options) -> Result<Stats> {
+    let start = gopher::parse_url(url);
+    if start.typ != Type::Menu {
+        return Err(error!("Can only mirror menus, not {:?}", start.typ));
+    }
+    let host = start.host.to_string();
+    let port = start.port.to_string();
+    let prefix = if opts.prefix {
+        start.sel.trim_end_matches('/').to_string()
+    } else {
+        String::new()
+    };
+    let in_scope = |u: &gopher::Url| u.host == host && u.port == port && in_prefix(u.sel, &prefix);
+
+    let mut stats = Stats::default();
+    let mut paths = Paths::default();
+    let mut queue = VecDeque::new();
+    let start_path = paths.claim(start.typ, start.sel);
+    queue.push_back((url.to_string(), start_path, 0));
+
+    while let Some((url, saved_at, depth)) = queue.pop_front() {
+        let u = gopher::parse_url(&url);
+        let path = opts.output.join(&saved_at);
+
+        if u.typ != Type::Menu {
+            if path.exists() {
+                println!("-- {} (already saved)", url);
+                stats.skipped += 1;
+                continue;
+            }
+            match save_item(&url, &path, opts) {
+                Ok(()) => {
+                    println!("-> {}", url);
+                    stats.fetched += 1;
+                }
+                Err(e) => {
+                    eprintln!("!! {}: {}", url, e);
+                    stats.failed += 1;
+                }
+            }
+            continue;
+        }
+
+        let raw = match gopher::fetch_url(&url, opts.tls, opts.tor) {
+            Ok((_, raw)) => raw,
+            Err(e) => {
+                eprintln!("!! {}: {}", url, e);
+                stats.failed += 1;
+                thread::sleep(DELAY);
+                continue;
+            }
+        };
+        thread::sleep(DELAY);
+        println!("-> {}", url);
+        stats.fetched += 1;
+
+        let mut out = String::new();
+        for raw_line in raw.split_terminator('\n') {
+            let raw_line = raw_line.trim_end_matches('\r');
+            if raw_line == "." {
+                break;
+            }
+            let line = match menu::parse_line(0, raw_line) {
+                Some(line) if line.typ.is_link() => line,
+                Some(_) if !raw_line.contains('\t') => {
+                    // saved gophermaps treat tab-less lines as text
+                    out.push_str(&format!("{}\t\terror.host\t1\r\n", raw_line));
+                    continue;
+                }
+                _ => {
+                    out.push_str(raw_line);
+                    out.push_str("\r\n");
+                    continue;
+                }
+            };
+            let link = line.url(raw_line);
+            let target = gopher::parse_url(&link);
+            let local = depth < opts.depth
+                && mirrorable(line.typ)
+                && link.starts_with("gopher://")
+                && in_scope(&target);
+
+            if local {
+                let (target_path, new) = match paths.get(target.typ, target.sel) {
+                    Some(path) => (path.clone(), false),
+                    None => (paths.claim(target.typ, target.sel), true),
+                };
+                let rel = relative(&saved_at, &target_path);
+                out.push_str(&format!(
+                    "{}{}\t{}\t\t\r\n",
+                    line.typ,
+                    line.text(raw_line),
+                    rel
+                ));
+                if new {
+                    queue.push_back((link, target_path, depth + 1));
+                }
+            } else {
+                out.push_str(raw_line);
+                out.push_str("\r\n");
+            }
+        }
+
+        let dir = path;
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(GOPHERMAP), out)) {
+            eprintln!("!! {}: {}", url, e);
+            stats.failed += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Default output directory for a mirror: the host, plus the port if
+/// it isn't 70.
+pub fn default_output(url: &str) -> String {
+    let u = gopher::parse_url(url);
+    let host = u.host.replace(':', "_");
+    if u.port == "70" {
+        host
+    } else {
+        format!("{}_{}", host, u.port)
+    }
+}
+
+/// Is `sel` the selector `prefix` or somewhere under it? `/a/b` is
+/// under `/a`, but `/ab` isn't.
+fn in_prefix(sel: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match sel.strip_prefix(prefix) {
+        Some(rest) => prefix.is_empty() || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Types we download. Searches, telnet, and external links can't be
+/// mirrored.
+fn mirrorable(typ: Type) -> bool {
+    typ == Type::Menu || typ == Type::Text || typ == Type::HTML || typ.is_download()
+}
+
+/// Download a single non-menu item to disk.
+fn save_item(url: &str, path: &Path, opts: &Options) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let u = gopher::parse_url(url);
+    let res = gopher::request(u.host, u.port, u.sel, opts.tls, opts.tor)
+        .and_then(|mut stream| io::copy(&mut stream, &mut fs::File::create(&part)?))
+        .and_then(|_| fs::rename(&part, path));
+    thread::sleep(DELAY);
+    res
+}
+
+/// Where each mirrored item is saved, relative to the mirror's
+/// directory. Items are keyed by type and selector, so a text file
+/// and a menu with the same selector are saved separately.
+#[derive(Default)]
+struct Paths {
+    /// Path of each item, by `key()`.
+    items: HashMap<String, PathBuf>,
+    /// Paths saved to as files.
+    files: HashSet<PathBuf>,
+    /// Paths that have to be directories: menus and the parents of
+    /// everything.
+    dirs: HashSet<PathBuf>,
+    /// Directories a menu's `gophermap` is saved in.
+    menus: HashSet<PathBuf>,
+}
+
+impl Paths {
+    /// Where an item is saved, if it's been claimed.
+    fn get(&self, typ: Type, sel: &str) -> Option<&PathBuf> {
+        self.items.get(&key(typ, sel))
+    }
+
+    /// Pick where to save an item. Each part of its selector is used
+    /// as-is, unless a file already has that name where a directory is
+    /// needed or the other way around, then a number is added to it.
+    fn claim(&mut self, typ: Type, sel: &str) -> PathBuf {
+        let menu = typ == Type::Menu;
+        let parts: Vec<_> = local_path(sel).iter().map(|p| p.to_owned()).collect();
+        let mut path = PathBuf::new();
+        for (i, part) in parts.iter().enumerate() {
+            let is_dir = menu || i + 1 < parts.len();
+            let mut name = part.to_owned();
+            for n in 1.. {
+                let next = path.join(&name);
+                let free = if !is_dir {
+                    !self.files.contains(&next) && !self.dirs.contains(&next)
+                } else if i + 1 == parts.len() {
+                    !self.files.contains(&next) && !self.menus.contains(&next)
+                } else {
+                    !self.files.contains(&next)
+                };
+                if free {
+                    break;
+                }
+                name = part.to_owned();
+                name.push(format!("_{}", n));
+            }
+            path.push(name);
+            if is_dir {
+                self.dirs.insert(path.clone());
+            }
+        }
+        if menu {
+            self.menus.insert(path.clone());
+        } else {
+            self.files.insert(path.clone());
+        }
+        self.items.insert(key(typ, sel), path.clone());
+        path
+    }
+}
+
+/// The same item can be linked to with or without a trailing slash.
+fn key(typ: Type, sel: &str) -> String {
+    format!("{}{}", typ, sel.trim_end_matches('/'))
+}
+
+/// Where a selector would be saved, relative to the mirror's
+/// directory. Unsafe path parts like `..` are replaced.
+fn local_path(sel: &str) -> PathBuf {
+    sel.split(&['/', '\\'][..])
+        .filter(|p| !p.is_empty() && *p != ".")
+        .map(|p| {
+            if p == ".." {
+                "_".to_string()
+            } else {
+                p.replace(|c: char| c == '\t' || c == '?' || c.is_control(), "_")
+            }
+        })
+        .collect()
+}
+
+/// Relative selector from the menu saved at `from` to `to`.
+fn relative(from: &Path, to: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec!["..".to_string(); from.len() - common];
+    for part in &to[common..] {
+        parts.push(part.as_os_str().to_string_lossy().to_string());
+    }
+    if parts.is_empty() {
+        "./".into()
+    } else {
+        parts.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path(""), PathBuf::new());
+        assert_eq!(local_path("/"), PathBuf::new());
+        assert_eq!(local_path("/phlog/"), PathBuf::from("phlog"));
+        assert_eq!(
+            local_path("phlog/first.txt"),
+            PathBuf::from("phlog/first.txt")
+        );
+        assert_eq!(local_path("/../../etc"), PathBuf::from("_/_/etc"));
+        assert_eq!(local_path("/q?x"), PathBuf::from("q_x"));
+    }
+
+    #[test]
+    fn test_paths() {
+        let mut paths = Paths::default();
+        assert_eq!(paths.claim(Type::Menu, "/"), PathBuf::new());
+        assert_eq!(paths.claim(Type::Text, "/x"), PathBuf::from("x"));
+        // a menu and a file can't both be x
+        assert_eq!(paths.claim(Type::Menu, "/x/"), PathBuf::from("x_1"));
+        assert_eq!(paths.claim(Type::Text, "/x/y"), PathBuf::from("x_1/y"));
+        assert_eq!(paths.claim(Type::Binary, "/x"), PathBuf::from("x_2"));
+        assert_eq!(paths.get(Type::Menu, "/x"), Some(&PathBuf::from("x_1")));
+        assert_eq!(paths.get(Type::Text, "/x"), Some(&PathBuf::from("x")));
+        assert_eq!(paths.get(Type::Text, "/z"), None);
+
+        // the other way around
+        let mut paths = Paths::default();
+        assert_eq!(paths.claim(Type::Menu, "/a"), PathBuf::from("a"));
+        assert_eq!(paths.claim(Type::Text, "/a"), PathBuf::from("a_1"));
+    }
+
+    #[test]
+    fn test_relative() {
+        let rel = |a: &str, b: &str| relative(Path::new(a), Path::new(b));
+        assert_eq!(rel("", "phlog"), "phlog");
+        assert_eq!(rel("phlog", "phlog/first.txt"), "first.txt");
+        assert_eq!(rel("phlog/2020", "phlog"), "..");
+        assert_eq!(rel("phlog/2020", "about.txt"), "../../about.txt");
+        assert_eq!(rel("phlog", "phlog"), "./");
+    }
+
+    #[test]
+    fn test_in_prefix() {
+        assert!(in_prefix("/a", "/a"));
+        assert!(in_prefix("/a/", "/a"));
+        assert!(in_prefix("/a/b.txt", "/a/"));
+        assert!(!in_prefix("/ab", "/a"));
+        assert!(!in_prefix("/b/a", "/a"));
+        assert!(in_prefix("/anything", ""));
+    }
+
+    #[test]
+    fn test_default_output() {
+        assert_eq!(default_output("gopher://sdf.org/1/maps"), "sdf.org");
+        assert_eq!(default_output("127.0.0.1:7070"), "127.0.0.1_7070");
+    }
+}
@@ -42,16 +42,7 @@ pub fn append(filename: &str, label: &str, url: &str) -> Result<()> {
     path().and_then(|dotdir| {
         let path = dotdir.join(filename);
         if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(path) {
-            let u = gopher::parse_url(&url);
-            write!(
-                file,
-                "{}{}\t{}\t{}\t{}\r\n",
-                u.typ.to_char().unwrap_or('i'),
-                label,
-                u.sel,
-                u.host,
-                u.port
-            )?;
+            write!(file, "{}\r\n", menu_line(label, url))?;
             Ok(())
         } else {
             Err(error!("Can't open file for writing: {:?}", filename))
@@ -69,19 +60,10 @@ pub fn prepend(filename: &str, label: &str, url: &str) -> Result<()> {
             .create(true)
             .open(path)
         {
-            let url = gopher::parse_url(&url);
             let mut buf = vec![];
             file.read_to_end(&mut buf)?;
             file.seek(std::io::SeekFrom::Start(0))?;
-            write!(
-                file,
-                "{}{}\t{}\t{}\t{}\r\n",
-                url.typ.to_char().unwrap_or('i'),
-                label,
-                url.sel,
-                url.host,
-                url.port
-            )?;
+            write!(file, "{}\r\n", menu_line(label, url))?;
             file.write_all(&buf)?;
             Ok(())
         } else {
@@ -90,6 +72,40 @@ pub fn prepend(filename: &str, label: &str, url: &str) -> Result<()> {
     })
 }
 
+/// Replace a file in the phetchdir. The new contents are written to
+/// a temporary file first, so a failed write leaves the old file.
+pub fn save(filename: &str, contents: &str) -> Result<()> {
+    path().and_then(|dotdir| {
+        let path = dotdir.join(filename);
+        let tmp = dotdir.join(format!("{}.tmp", filename));
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&tmp, contents)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| error!("Can't save {:?}: {}", path, e))
+    })
+}
+
+/// Gophermap line linking to a URL, without the trailing CRLF.
+pub fn menu_line(label: &str, url: &str) -> String {
+    let u = gopher::parse_url(url);
+    // non-gopher URLs go in the selector, ex: URL:https://...
+    let sel = if u.host.is_empty() && u.sel.contains("://") {
+        format!("URL:{}", u.sel)
+    } else {
+        u.sel.to_string()
+    };
+    format!(
+        "{}{}\t{}\t{}\t{}",
+        u.typ.to_char().unwrap_or('i'),
+        label,
+        sel,
+        u.host,
+        u.port
+    )
+}
+
 /// Returns the full, expanded PathBuf of the phetchdir only if it exists.
 /// Returns None otherwise.
 /// If you just want the phetchdir path whether or not it exists, use
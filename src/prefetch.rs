@@ -0,0 +1,272 @@
+//! With `prefetch yes` in phetch.conf, phetch fetches links before
+//! you open them: the first few text and menu links on each menu you
+//! visit, and any link you leave the cursor on for a moment. Opening
+//! one of them is then instant.
+//!
+//! The split view's preview pane gets its pages from here too, so
+//! it's held to the same limits and shares the same cache.
+//!
+//! Prefetched pages are only kept in memory, in a `Cache`, until
+//! they're opened or go stale. At most `MAX_JOBS` requests run at
+//! once, and at most `MAX_PER_HOST` of them to the same server.
+
+use crate::{
+    cache::{self, Cache},
+    gopher::{self, Type},
+    menu,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::Result,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+
+/// How many links on each menu to prefetch.
+pub const LINKS: usize = 5;
+
+/// Most requests to run at once.
+pub const MAX_JOBS: usize = 4;
+
+/// Most requests to run at once to a single server.
+pub const MAX_PER_HOST: usize = 2;
+
+/// How long the cursor has to rest on a link before it's
+/// prefetched, in milliseconds.
+pub const REST_MS: i32 = 300;
+
+/// Shared between the UI and the request threads.
+#[derive(Default)]
+struct State {
+    /// Pages that have been prefetched.
+    cache: Cache,
+    /// URLs waiting to be fetched, next first.
+    queue: VecDeque<String>,
+    /// URLs being fetched right now.
+    fetching: HashSet<String>,
+    /// How many requests are running to each "host:port".
+    hosts: HashMap<String, usize>,
+    /// Why each URL that couldn't be fetched failed, for the menu
+    /// that's focused.
+    failed: HashMap<String, String>,
+}
+
+/// Runs prefetch requests in the background.
+#[derive(Clone)]
+struct Jobs {
+    state: Arc<Mutex<State>>,
+    tls: bool,
+    tor: bool,
+}
+
+/// Decides what to prefetch, and holds on to it.
+pub struct Prefetcher {
+    jobs: Jobs,
+    /// Queue the first links on each menu? Otherwise only the
+    /// selected link is fetched.
+    links: bool,
+    /// URL of the last menu whose links were queued.
+    menu: String,
+    /// The selected link, last we checked.
+    selected: Option<String>,
+    /// Has the selected link not been prefetched yet?
+    waiting: bool,
+}
+
+impl Prefetcher {
+    /// Create a Prefetcher that makes requests with these settings.
+    /// With `links` off it only fetches the selected link.
+    pub fn new(tls: bool, tor: bool, links: bool) -> Prefetcher {
+        Prefetcher {
+            jobs: Jobs {
+                state: Arc::new(Mutex::new(State::default())),
+                tls,
+                tor,
+            },
+            links,
+            menu: String::new(),
+            selected: None,
+            waiting: false,
+        }
+    }
+
+    /// A prefetched page, if we have it. It's removed from the cache,
+    /// so opening the link again fetches a fresh copy.
+    pub fn take(&self, url: &str) -> Option<cache::Page> {
+        self.jobs.state().cache.take(url)
+    }
+
+    /// A prefetched page or the reason it couldn't be fetched, if
+    /// we have either. The page stays in the cache.
+    pub fn get(&self, url: &str) -> Option<Result<cache::Page>> {
+        let mut state = self.jobs.state();
+        if let Some(page) = state.cache.get(url) {
+            return Some(Ok(page.clone()));
+        }
+        state.failed.get(url).map(|e| Err(error!("{}", e)))
+    }
+
+    /// Keep up with what's on screen. When a new menu is focused its
+    /// first links are queued, in place of the last menu's. A newly
+    /// selected link waits to be prefetched until `rested()`.
+    pub fn focus(&mut self, url: &str, raw: &str, selected: Option<String>) {
+        if self.menu != url {
+            self.menu = url.to_string();
+            let links = if self.links && gopher::type_for_url(url) == Type::Menu {
+                links(raw)
+            } else {
+                vec![]
+            };
+            {
+                let mut state = self.jobs.state();
+                state.queue = links.into_iter().collect();
+                state.failed.clear();
+            }
+            self.jobs.start();
+        }
+        if self.selected != selected {
+            self.waiting = selected.is_some();
+            self.selected = selected;
+        }
+    }
+
+    /// Is the selected link waiting to be prefetched?
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// The cursor has rested on the selected link, so fetch it ahead
+    /// of anything else in the queue.
+    pub fn rested(&mut self) {
+        self.waiting = false;
+        let url = match &self.selected {
+            Some(url) if can_prefetch(url) => url.clone(),
+            _ => return,
+        };
+        {
+            let mut state = self.jobs.state();
+            state.failed.remove(&url);
+            state.queue.retain(|u| *u != url);
+            state.queue.push_front(url);
+        }
+        self.jobs.start();
+    }
+}
+
+impl Jobs {
+    /// Lock the shared state. A thread that panicked holding it
+    /// can't have left it worse than half-updated, so carry on.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start fetching queued URLs, as many as the limits allow.
+    fn start(&self) {
+        let mut state = self.state();
+        while state.fetching.len() < MAX_JOBS {
+            let hosts = &state.hosts;
+            let next = state
+                .queue
+                .iter()
+                .position(|url| hosts.get(&host(url)).copied().unwrap_or(0) < MAX_PER_HOST);
+            let url = match next.and_then(|i| state.queue.remove(i)) {
+                Some(url) => url,
+                None => break,
+            };
+            if state.fetching.contains(&url) || state.cache.contains(&url) {
+                continue;
+            }
+            *state.hosts.entry(host(&url)).or_insert(0) += 1;
+            state.fetching.insert(url.clone());
+            let jobs = self.clone();
+            thread::spawn(move || jobs.fetch(url));
+        }
+    }
+
+    /// Fetch a URL into the cache, then start the next one.
+    fn fetch(&self, url: String) {
+        let res = gopher::fetch_url_bytes(&url, self.tls, self.tor);
+        {
+            let mut state = self.state();
+            state.fetching.remove(&url);
+            let host = host(&url);
+            let running = state.hosts.get(&host).copied().unwrap_or(0);
+            if running > 1 {
+                state.hosts.insert(host, running - 1);
+            } else {
+                state.hosts.remove(&host);
+            }
+            match res {
+                Ok(page) => state.cache.insert(url, page),
+                Err(e) => {
+                    state.failed.insert(url, e.to_string());
+                }
+            }
+        }
+        self.start();
+    }
+}
+
+/// Server a URL is on, as "host:port".
+fn host(url: &str) -> String {
+    let u = gopher::parse_url(url);
+    format!("{}:{}", u.host, u.port)
+}
+
+/// Is this a link we'd prefetch? Only text files and menus on
+/// Gopher servers are worth it.
+pub fn can_prefetch(url: &str) -> bool {
+    let typ = gopher::type_for_url(url);
+    url.starts_with("gopher://")
+        && !url.starts_with("gopher://phetch")
+        && (typ == Type::Text || typ == Type::Menu)
+}
+
+/// The first `LINKS` links worth prefetching in a Gophermap.
+fn links(raw: &str) -> Vec<String> {
+    raw.lines()
+        .filter_map(|line| Some(menu::parse_line(0, line)?.url(line)))
+        .filter(|url| can_prefetch(url))
+        .take(LINKS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        let raw = "iWelcome\t\t\t\r\n\
+                   1Phlog\t/phlog\tsdf.org\t70\r\n\
+                   9Archive\t/a.zip\tsdf.org\t70\r\n\
+                   hWeb\tURL:https://sdf.org\tsdf.org\t70\r\n\
+                   0About\t/about.txt\tsdf.org\t70\r\n\
+                   7Search\t/search\tsdf.org\t70\r\n";
+        assert_eq!(
+            links(raw),
+            vec!["gopher://sdf.org/1/phlog", "gopher://sdf.org/0/about.txt"]
+        );
+        let many = "0Text\t/t\tsdf.org\t70\r\n".repeat(LINKS + 2);
+        assert_eq!(links(&many).len(), LINKS);
+    }
+
+    #[test]
+    fn test_limits() {
+        let jobs = Prefetcher::new(false, false, true).jobs;
+        {
+            let mut state = jobs.state();
+            state.hosts.insert("sdf.org:70".into(), MAX_PER_HOST);
+            state.queue.push_back("gopher://sdf.org/0/a.txt".into());
+            state
+                .cache
+                .insert("gopher://b.org/0/b.txt".into(), (false, vec![]));
+            state.queue.push_back("gopher://b.org/0/b.txt".into());
+        }
+        jobs.start();
+        let state = jobs.state();
+        // sdf.org is busy, and b.org is already cached
+        assert_eq!(state.queue, vec!["gopher://sdf.org/0/a.txt"]);
+        assert!(state.fetching.is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+//! With `split yes` in phetch.conf, menus on wide screens only take
+//! up the left half of the screen. The right half previews the
+//! selected link: the start of a text file, or the items in a menu.
+//!
+//! Previews are fetched in the background by the `Prefetcher`, once
+//! the cursor has rested on a link, so moving the cursor never waits
+//! on the network or floods a server with requests. The UI checks in
+//! with `update()` while it's waiting for a keypress and redraws the
+//! pane when the page arrives.
+
+use crate::{
+    gopher::{self, Type},
+    menu,
+    prefetch::{self, Prefetcher},
+    terminal,
+};
+
+/// Narrowest screen that gets split. Any less and the menu would be
+/// cramped.
+pub const MIN_COLS: usize = 100;
+
+/// A link's content, as far as we know.
+enum Page {
+    Loading,
+    Loaded(String),
+    Failed(String),
+}
+
+/// Draws the preview pane.
+pub struct Preview {
+    /// URL being previewed, if any.
+    url: Option<String>,
+    /// Its content.
+    page: Page,
+    /// Has the pane changed since it was last drawn?
+    changed: bool,
+}
+
+impl Default for Preview {
+    fn default() -> Preview {
+        Preview {
+            url: None,
+            page: Page::Loading,
+            changed: false,
+        }
+    }
+}
+
+impl Preview {
+    /// Preview a URL, or nothing.
+    pub fn show(&mut self, url: Option<&str>) {
+        if self.url.as_deref() != url {
+            self.url = url.map(String::from);
+            self.page = Page::Loading;
+            self.changed = true;
+        }
+    }
+
+    /// Pick up the page being previewed, if it's been fetched.
+    pub fn update(&mut self, prefetch: &Prefetcher) {
+        if !self.is_loading() {
+            return;
+        }
+        let res = match self.url.as_ref().and_then(|url| prefetch.get(url)) {
+            Some(res) => res,
+            None => return,
+        };
+        self.page = match res {
+            Ok((_, body)) => Page::Loaded(gopher::decode(&body, None)),
+            Err(e) => Page::Failed(e.to_string()),
+        };
+        self.changed = true;
+    }
+
+    /// Is the link being previewed still loading?
+    pub fn is_loading(&self) -> bool {
+        let url = self.url.as_deref().filter(|url| can_preview(url));
+        url.is_some() && matches!(self.page, Page::Loading)
+    }
+
+    /// Does the pane need to be drawn again?
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Draw the pane, starting at column `x` and taking up `cols`
+    /// columns and all but the last of `rows`.
+    pub fn render(&mut self, x: usize, (cols, rows): (usize, usize)) -> String {
+        self.changed = false;
+        let width = cols.saturating_sub(3);
+        let height = rows.saturating_sub(1);
+        let lines = match &self.url {
+            Some(url) => lines(url, &self.page, width, height),
+            None => vec![],
+        };
+        let mut out = String::new();
+        for row in 0..height {
+            out.push_str(&format!(
+                "{}{} {}{}",
+                terminal::Goto(x as u16, row as u16 + 1),
+                color_string!("│", Grey),
+                lines.get(row).map_or("", String::as_str),
+                terminal::ClearUntilNewline
+            ));
+        }
+        out
+    }
+}
+
+/// Can we show what's at this URL in the pane? Only what we'd
+/// prefetch.
+fn can_preview(url: &str) -> bool {
+    prefetch::can_prefetch(url)
+}
+
+/// Lines of the pane for a URL, no wider than `width` and no more
+/// than `height` of them.
+fn lines(url: &str, page: &Page, width: usize, height: usize) -> Vec<String> {
+    let clip = |s: &str| -> String {
+        s.replace('\t', "    ")
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(width)
+            .collect()
+    };
+    let mut out = vec![color_string!(clip(url), Grey), String::new()];
+    match page {
+        _ if !can_preview(url) => out.push("No preview.".into()),
+        Page::Loading => out.push("Loading...".into()),
+        Page::Failed(e) => out.push(color_string!(clip(e), Red)),
+        Page::Loaded(body) if gopher::type_for_url(url) == Type::Menu => {
+            for line in body.lines() {
+                let l = match menu::parse_line(0, line) {
+                    Some(l) => l,
+                    None => continue,
+                };
+                let text = clip(l.text(line));
+                out.push(match l.typ {
+                    Type::Info => text,
+                    Type::Menu => color_string!(text, Blue),
+                    Type::Text => color_string!(text, Cyan),
+                    _ => color_string!(text, White),
+                });
+            }
+        }
+        Page::Loaded(body) => out.extend(body.lines().map(clip)),
+    }
+    out.truncate(height);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let url = "gopher://sdf.org/1/phlogs";
+        let menu = Page::Loaded("iPhlogs\t\t\t\r\n1A very long title\t/a\tsdf.org\t70\r\n".into());
+        let out = lines(url, &menu, 6, 10);
+        assert_eq!(out.len(), 4);
+        assert_eq!(out[2], "Phlogs");
+        assert_eq!(out[3], color_string!("A very", Blue));
+
+        let url = "gopher://sdf.org/0/tips.txt";
+        let text = Page::Loaded("one\ttwo\nthree\nfour\n".into());
+        let out = lines(url, &text, 80, 4);
+        assert_eq!(&out[2..], &["one    two", "three"]);
+
+        let out = lines("gopher://sdf.org/9/file.zip", &Page::Loading, 80, 4);
+        assert_eq!(out[2], "No preview.");
+    }
+}
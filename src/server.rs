@@ -0,0 +1,155 @@
+//! phetch can serve a local directory over Gopher with `--serve`.
+//! It's meant for previewing a gopherhole before publishing it, and
+//! doubles as a handy local server for testing phetch itself.
+//!
+//! Directories with a `gophermap` file serve it, and so do `.gph`
+//! files, with relative selectors resolved against their directory.
+//! Directories without one get a generated menu. Everything else is
+//! sent as-is.
+
+use crate::{
+    gopher::{Type, TCP_TIMEOUT_DURATION},
+    gophermap,
+};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Result, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    thread,
+};
+
+/// Address the server listens on. Same as `phetch --local`.
+pub const HOST: &str = "127.0.0.1";
+
+/// Default port for `--serve`. Same as `phetch --local`.
+pub const DEFAULT_PORT: u16 = 7070;
+
+/// Serve `dir` over Gopher on the given port until killed.
+pub fn run(dir: &str, port: u16) -> Result<()> {
+    let root = fs::canonicalize(dir)?;
+    if !root.is_dir() {
+        return Err(error!("Not a directory: {}", dir));
+    }
+    let listener = TcpListener::bind((HOST, port))?;
+    println!(
+        "-> Serving {} at gopher://{}:{}",
+        root.to_string_lossy(),
+        HOST,
+        port
+    );
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                log!("accept error: {}", e);
+                continue;
+            }
+        };
+        let root = root.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &root, port) {
+                log!("request error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Respond to a single Gopher request.
+fn handle(mut stream: TcpStream, root: &Path, port: u16) -> Result<()> {
+    stream.set_read_timeout(Some(TCP_TIMEOUT_DURATION))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let selector = line
+        .trim_end_matches(&['\r', '\n'][..])
+        .split('\t')
+        .next()
+        .unwrap_or("");
+    log!("serve: /{}", selector.trim_start_matches('/'));
+
+    let path = match path_for_selector(root, selector) {
+        Some(path) if path.exists() => path,
+        _ => {
+            stream.write_all(error_menu(&format!("Not found: {}", selector)).as_ref())?;
+            return Ok(());
+        }
+    };
+
+    if path.is_dir() || gophermap::is_gophermap(&path) {
+        stream.write_all(menu_for(root, &path, port)?.as_ref())?;
+        stream.write_all(b".\r\n")?;
+    } else {
+        stream.write_all(&fs::read(&path)?)?;
+    }
+    Ok(())
+}
+
+/// Turn a selector into a path inside `root`. Returns None if the
+/// selector tries to escape it.
+fn path_for_selector(root: &Path, selector: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for part in Path::new(selector.trim_start_matches('/')).components() {
+        match part {
+            Component::Normal(p) => {
+                // hidden files aren't served
+                if p.to_string_lossy().starts_with('.') {
+                    return None;
+                }
+                path.push(p);
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// The Gophermap for a directory or a `.gph` file: either the file
+/// (or the directory's `gophermap` file), or a generated listing.
+fn menu_for(root: &Path, path: &Path, port: u16) -> Result<String> {
+    let linker = |path: &Path| {
+        let sel = match path.strip_prefix(root) {
+            Ok(rel) => format!("/{}", rel.to_string_lossy()),
+            Err(_) => path.to_string_lossy().to_string(),
+        };
+        (sel, HOST.to_string(), port.to_string())
+    };
+
+    let (map, dir) = if path.is_dir() {
+        (path.join(gophermap::GOPHERMAP), path)
+    } else {
+        (path.to_path_buf(), path.parent().unwrap_or(root))
+    };
+    if map.exists() {
+        let raw = String::from_utf8_lossy(&fs::read(&map)?).to_string();
+        Ok(gophermap::resolve(&raw, root, dir, &linker))
+    } else {
+        let (title, _, _) = linker(dir);
+        gophermap::for_dir(dir, &title, &linker)
+    }
+}
+
+/// A menu with a single error line.
+fn error_menu(msg: &str) -> String {
+    format!("{}{}\t\terror.host\t1\r\n.\r\n", Type::Error, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_for_selector() {
+        let root = Path::new("/srv/gopher");
+        assert_eq!(path_for_selector(root, ""), Some(root.to_path_buf()));
+        assert_eq!(path_for_selector(root, "/"), Some(root.to_path_buf()));
+        assert_eq!(
+            path_for_selector(root, "/phlog/./first.txt"),
+            Some(root.join("phlog/first.txt"))
+        );
+        assert_eq!(path_for_selector(root, "/../etc/passwd"), None);
+        assert_eq!(path_for_selector(root, "/.git/config"), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! phetch can reopen the pages you had open last time. With `restore
+//! yes` in phetch.conf, or the `--restore` flag, the open pages are
+//! saved to `SESSION_FILE` in the phetchdir on quit.
+//!
+//! The session file is a Gophermap, one link per page, with a few
+//! extra fields on each line: the scroll offset, the selected link,
+//! wide mode, whether it's the page you were looking at (`yes`) or
+//! the page another tab was showing (`tab`), and which tab it's in.
+//!
+//! Restored pages aren't fetched until you go back or forward to
+//! them. Until then, each is a `Lazy` view that only remembers where
+//! you were on it.
+
+use crate::{
+    menu, phetchdir, terminal,
+    ui::{Action, Key, View},
+};
+use std::{
+    fmt,
+    io::{Read, Result},
+};
+
+/// Open pages are saved here.
+const SESSION_FILE: &str = "session.gph";
+
+/// Where you were on a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// Page title.
+    pub title: String,
+    /// Gopher URL.
+    pub url: String,
+    /// Scroll offset, in lines.
+    pub scroll: usize,
+    /// Index of the selected link, if it has links.
+    pub link: Option<usize>,
+    /// In wide mode?
+    pub wide: bool,
+}
+
+/// The pages open in a tab, oldest first.
+#[derive(Debug, Default, PartialEq)]
+pub struct Tab {
+    /// Every page you can go back or forward to.
+    pub pages: Vec<Page>,
+    /// Index of the page the tab was showing.
+    pub focused: usize,
+}
+
+/// The open tabs.
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    /// Every tab, in order.
+    pub tabs: Vec<Tab>,
+    /// Index of the tab you were looking at.
+    pub tab: usize,
+}
+
+impl Session {
+    /// Parse a session file. Lines that aren't links are skipped.
+    pub fn parse(raw: &str) -> Session {
+        let mut session = Session::default();
+        let mut numbers: Vec<&str> = vec![];
+        for line in raw.lines().map(|l| l.trim_end_matches('\r')) {
+            let l = match menu::parse_line(0, line) {
+                Some(l) if l.typ.is_link() => l,
+                _ => continue,
+            };
+            let extra: Vec<&str> = line.split('\t').skip(4).collect();
+            let field = |i: usize| extra.get(i).map(|s| s.trim()).unwrap_or("");
+            let t = match numbers.iter().position(|n| *n == field(4)) {
+                Some(t) => t,
+                None => {
+                    numbers.push(field(4));
+                    session.tabs.push(Tab::default());
+                    session.tabs.len() - 1
+                }
+            };
+            let tab = &mut session.tabs[t];
+            match field(3) {
+                "yes" => {
+                    session.tab = t;
+                    tab.focused = tab.pages.len();
+                }
+                "tab" => tab.focused = tab.pages.len(),
+                _ => {}
+            }
+            tab.pages.push(Page {
+                title: l.text(line).to_string(),
+                url: l.url(line),
+                scroll: field(0).parse().unwrap_or(0),
+                link: field(1).parse().ok(),
+                wide: field(2) == "yes",
+            });
+        }
+        session
+    }
+
+    /// Load the last session. Empty if there isn't one.
+    pub fn load() -> Result<Session> {
+        if !phetchdir::exists(SESSION_FILE) {
+            return Ok(Session::default());
+        }
+        let mut raw = String::new();
+        phetchdir::load(SESSION_FILE)?.read_to_string(&mut raw)?;
+        Ok(Session::parse(&raw))
+    }
+
+    /// Save this session to the phetchdir, replacing the last one.
+    pub fn save(&self) -> Result<()> {
+        phetchdir::save(SESSION_FILE, &self.to_string())
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (t, tab) in self.tabs.iter().enumerate() {
+            for (i, page) in tab.pages.iter().enumerate() {
+                let focused = match (i == tab.focused, t == self.tab) {
+                    (true, true) => "yes",
+                    (true, false) => "tab",
+                    _ => "no",
+                };
+                write!(
+                    f,
+                    "{}\t{}\t{}\t{}\t{}\t{}\r\n",
+                    phetchdir::menu_line(&page.title, &page.url),
+                    page.scroll,
+                    page.link.map(|l| l.to_string()).unwrap_or_default(),
+                    if page.wide { "yes" } else { "no" },
+                    focused,
+                    t + 1
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A restored page that hasn't been fetched yet. The UI swaps it out
+/// for the real thing when it's focused.
+pub struct Lazy {
+    page: Page,
+}
+
+impl Lazy {
+    /// Create a Lazy view for a page from the last session.
+    pub fn from(page: Page) -> Lazy {
+        Lazy { page }
+    }
+}
+
+impl fmt::Display for Lazy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.url())
+    }
+}
+
+impl View for Lazy {
+    fn respond(&mut self, key: Key) -> Action {
+        Action::Keypress(key)
+    }
+
+    fn render(&mut self) -> String {
+        format!(
+            "\r\n  {}{}\r\n\r\n  Not loaded.{}\r\n{}",
+            self.page.url,
+            terminal::ClearUntilNewline,
+            terminal::ClearUntilNewline,
+            terminal::ClearAfterCursor
+        )
+    }
+
+    fn is_loaded(&self) -> bool {
+        false
+    }
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+
+    fn is_tor(&self) -> bool {
+        false
+    }
+
+    fn url(&self) -> &str {
+        &self.page.url
+    }
+
+    fn raw(&self) -> &str {
+        ""
+    }
+
+    fn set_wide(&mut self, wide: bool) {
+        self.page.wide = wide;
+    }
+
+    fn wide(&mut self) -> bool {
+        self.page.wide
+    }
+
+    fn scroll(&self) -> usize {
+        self.page.scroll
+    }
+
+    fn set_scroll(&mut self, scroll: usize) {
+        self.page.scroll = scroll;
+    }
+
+    fn selected_link(&self) -> Option<usize> {
+        self.page.link
+    }
+
+    fn set_selected_link(&mut self, link: usize) {
+        self.page.link = Some(link);
+    }
+
+    fn selected_url(&self) -> Option<String> {
+        None
+    }
+
+    fn term_size(&mut self, _cols: usize, _rows: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let page = |title: &str, url: &str, scroll, link, wide| Page {
+            title: title.into(),
+            url: url.into(),
+            scroll,
+            link,
+            wide,
+        };
+        let session = Session {
+            tabs: vec![
+                Tab {
+                    pages: vec![
+                        page("SDF", "gopher://sdf.org", 0, Some(4), false),
+                        page("Tips", "gopher://sdf.org/0/tips.txt", 12, None, true),
+                    ],
+                    focused: 1,
+                },
+                Tab {
+                    pages: vec![page("Help", "gopher://phetch/1/help", 0, Some(0), false)],
+                    focused: 0,
+                },
+            ],
+            tab: 1,
+        };
+        let raw = session.to_string();
+        assert!(raw.starts_with("1SDF\t\tsdf.org\t70\t0\t4\tno\tno\t1\r\n"));
+        assert!(raw.contains("0Tips\t/tips.txt\tsdf.org\t70\t12\t\tyes\ttab\t1\r\n"));
+        assert!(raw.ends_with("\t0\t0\tno\tyes\t2\r\n"));
+        assert_eq!(Session::parse(&raw), session);
+    }
+
+    #[test]
+    fn test_parse() {
+        // plain Gophermaps work too
+        let session = Session::parse("iMy pages\r\n1SDF\t/\tsdf.org\t70\r\n");
+        assert_eq!(session.tab, 0);
+        assert_eq!(session.tabs.len(), 1);
+        assert_eq!(session.tabs[0].pages[0].url, "gopher://sdf.org");
+        assert_eq!(session.tabs[0].pages[0].link, None);
+        assert_eq!(Session::parse(""), Session::default());
+    }
+}
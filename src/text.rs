@@ -49,6 +49,10 @@ impl View for Text {
         self.tor
     }
 
+    fn is_loaded(&self) -> bool {
+        true
+    }
+
     fn url(&self) -> &str {
         self.url.as_ref()
     }
@@ -69,6 +73,24 @@ impl View for Text {
         self.wide
     }
 
+    fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    fn set_scroll(&mut self, scroll: usize) {
+        self.scroll = scroll.min(self.lines.saturating_sub(1));
+    }
+
+    fn selected_link(&self) -> Option<usize> {
+        None
+    }
+
+    fn set_selected_link(&mut self, _link: usize) {}
+
+    fn selected_url(&self) -> Option<String> {
+        None
+    }
+
     fn respond(&mut self, c: Key) -> Action {
         match c {
             Key::Home => {
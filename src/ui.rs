@@ -13,22 +13,34 @@
 
 mod action;
 mod mode;
+mod tree;
 mod view;
+use self::tree::Tree;
 pub use self::{action::Action, mode::Mode, view::View};
 
 use crate::{
-    bookmarks, color,
+    bookmarks::{self, Bookmarks},
+    caps::{self, Caps},
+    color,
     config::Config,
+    export, finder,
     gopher::{self, Type},
-    help, history,
+    gophermap, help, history, html, linkcheck,
     menu::Menu,
+    phetchdir,
+    prefetch::{self, Prefetcher},
+    preview::{self, Preview},
+    session::{self, Lazy, Session},
     terminal,
     text::Text,
     utils, BUG_URL,
 };
 use std::{
     cell::RefCell,
-    io::{stdin, stdout, Result, Stdout, Write},
+    collections::HashMap,
+    fs,
+    io::{self, stdout, Read, Result, Stdout, Write},
+    path::Path,
     process::{self, Stdio},
     sync::mpsc,
     thread,
@@ -46,6 +58,20 @@ pub type Key = termion::event::Key;
 /// How many lines to jump by when using page up/down.
 pub const SCROLL_LINES: usize = 15;
 
+/// The focused tab's history tree. Links on it jump to a page, ex:
+/// gopher://phetch/1/tree/3
+const TREE_URL: &str = "gopher://phetch/1/tree";
+
+/// The report from checking a menu's links.
+const LINKCHECK_URL: &str = "gopher://phetch/1/linkcheck";
+
+/// How often to check on a loading preview while waiting for a
+/// keypress, in milliseconds.
+const PREVIEW_POLL_MS: i32 = 100;
+
+/// Most matches to show at once in the finder and tab list.
+const FINDER_ROWS: usize = 10;
+
 /// How big the longest line can be, for the purposes of calculating
 /// margin sizes. We often draw longer lines than this and allow
 /// wrapping in text views.
@@ -59,13 +85,35 @@ const ERR_RAW_MODE: &str = "Fatal Error using Raw Mode.";
 const ERR_SCREEN: &str = "Fatal Error using Alternate Screen.";
 const ERR_STDOUT: &str = "Fatal Error writing to STDOUT.";
 
+/// A tab has its own stack of Views to go back and forward in.
+#[derive(Default)]
+struct Tab {
+    /// Views in this tab, oldest first.
+    views: Vec<Box<dyn View>>,
+    /// Index of the View the tab is showing.
+    focused: usize,
+    /// Branches the tab has been down.
+    tree: Tree<Box<dyn View>>,
+}
+
 /// UI is mainly concerned with drawing to the screen, managing the
 /// active views, and responding to user input.
 pub struct UI {
-    /// Current loaded Gopher views. Menu or Text
+    /// Current loaded Gopher views in the focused tab. Menu or Text
     views: Vec<Box<dyn View>>,
     /// Index of currently focused View
     focused: usize,
+    /// Every page opened in the focused tab, including ones on
+    /// branches other than `views`.
+    tree: Tree<Box<dyn View>>,
+    /// While the history tree page is shown, the `views` and
+    /// `focused` it's shown in place of.
+    tree_page: Option<(Vec<Box<dyn View>>, usize)>,
+    /// Open tabs. The focused tab's Views live in `views` and
+    /// `focused` while it's focused, so its slot here is empty.
+    tabs: Vec<Tab>,
+    /// Index of the focused tab.
+    tab: usize,
     /// Does the UI need to be entirely redrawn?
     dirty: bool,
     /// Is the UI running?
@@ -76,6 +124,18 @@ pub struct UI {
     status: String,
     /// User config. Command line options + phetch.conf
     config: Config,
+    /// Each server's caps.txt, keyed by "host:port". None if it
+    /// doesn't have one.
+    caps: HashMap<String, Option<Caps>>,
+    /// Titles of the pages we've opened, keyed by URL. Suggested as
+    /// bookmark labels.
+    titles: HashMap<String, String>,
+    /// The selected link's content, shown next to menus in split mode.
+    preview: Preview,
+    /// Fetches links before they're opened: the first few on each
+    /// menu if `prefetch` is on, and the one the cursor rests on if
+    /// it's on or the screen is split.
+    prefetch: Prefetcher,
     out: RefCell<RawTerminal<Stdout>>,
 }
 
@@ -93,14 +153,24 @@ impl UI {
         let out = stdout().into_raw_mode().expect(ERR_RAW_MODE);
         out.suspend_raw_mode().expect(ERR_RAW_MODE);
 
+        let prefetch = Prefetcher::new(config.tls, config.tor, config.prefetch);
+
         UI {
             views: vec![],
             focused: 0,
+            tree: Tree::default(),
+            tree_page: None,
+            tabs: vec![Tab::default()],
+            tab: 0,
             dirty: true,
             running: true,
             size,
             config,
             status: String::new(),
+            caps: HashMap::new(),
+            titles: HashMap::new(),
+            preview: Preview::default(),
+            prefetch,
             out: RefCell::new(out),
         }
     }
@@ -128,11 +198,329 @@ impl UI {
             self.update();
         }
         self.shutdown();
+        if self.config.restore && !self.config.private {
+            self.save_session()?;
+        }
         Ok(())
     }
 
+    /// Reopen the tabs from the last session. Only the page that was
+    /// focused is fetched now, the rest when they're focused. Returns
+    /// false if there was nothing to restore.
+    pub fn restore(&mut self) -> Result<bool> {
+        let session = Session::load()?;
+        if session.tabs.is_empty() {
+            return Ok(false);
+        }
+        for page in session.tabs.iter().flat_map(|tab| &tab.pages) {
+            if page.title != page.url {
+                self.titles.insert(page.url.clone(), page.title.clone());
+            }
+        }
+        let current = session.tab.min(session.tabs.len() - 1);
+        let tab = &session.tabs[current];
+        let focused = tab.focused.min(tab.pages.len() - 1);
+        // fetch before adding any views, so there's no spinner before
+        // the UI has started
+        let view = self.load_page(&tab.pages[focused]);
+        self.tabs = session
+            .tabs
+            .into_iter()
+            .map(|tab| Tab {
+                focused: tab.focused.min(tab.pages.len().saturating_sub(1)),
+                tree: Tree::branch(tab.pages.len()),
+                views: tab
+                    .pages
+                    .into_iter()
+                    .map(|page| Box::new(Lazy::from(page)) as Box<dyn View>)
+                    .collect(),
+            })
+            .collect();
+        self.tab = current;
+        self.unpark_tab();
+        match view {
+            Ok(view) => self.views[focused] = view,
+            Err(e) => self.set_status(&format!("{}{}", color::Red, e)),
+        }
+        Ok(true)
+    }
+
+    /// Save the open tabs, for `restore()` to reopen next time.
+    fn save_session(&mut self) -> Result<()> {
+        self.close_tree_page();
+        let mut tabs = vec![];
+        for t in 0..self.tabs.len() {
+            let (views, mut focused) = if t == self.tab {
+                (&mut self.views, self.focused)
+            } else {
+                let tab = &mut self.tabs[t];
+                (&mut tab.views, tab.focused)
+            };
+            let mut pages = vec![];
+            for (i, view) in views.iter_mut().enumerate() {
+                // link check reports can't be reloaded
+                if view.url() == LINKCHECK_URL {
+                    if i < focused {
+                        focused -= 1;
+                    }
+                    continue;
+                }
+                let url = view.url().to_string();
+                pages.push(session::Page {
+                    title: self
+                        .titles
+                        .get(&url)
+                        .cloned()
+                        .unwrap_or_else(|| url.clone()),
+                    url,
+                    scroll: view.scroll(),
+                    link: view.selected_link(),
+                    wide: view.wide(),
+                });
+            }
+            let focused = focused.min(pages.len().saturating_sub(1));
+            tabs.push(session::Tab { pages, focused });
+        }
+        Session {
+            tabs,
+            tab: self.tab,
+        }
+        .save()
+    }
+
+    /// Fetch a page restored from the last session and put it back
+    /// the way it was.
+    fn load_page(&mut self, page: &session::Page) -> Result<Box<dyn View>> {
+        let title = self.titles.get(&page.url).cloned();
+        let mut view = self.load(title.as_deref().unwrap_or(&page.url), &page.url)?;
+        view.term_size(self.size.0, self.size.1);
+        view.set_wide(page.wide);
+        if let Some(link) = page.link {
+            view.set_selected_link(link);
+        }
+        view.set_scroll(page.scroll);
+        Ok(view)
+    }
+
+    /// If the focused View was restored from the last session but
+    /// hasn't been fetched yet, fetch it.
+    fn load_focused(&mut self) -> Result<()> {
+        let page = match self.views.get_mut(self.focused) {
+            Some(view) if !view.is_loaded() => session::Page {
+                title: String::new(),
+                url: view.url().to_string(),
+                scroll: view.scroll(),
+                link: view.selected_link(),
+                wide: view.wide(),
+            },
+            _ => return Ok(()),
+        };
+        let view = self.load_page(&page)?;
+        self.views[self.focused] = view;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Open a URL in a new tab, right after the focused one.
+    fn open_tab(&mut self, title: &str, url: &str) -> Result<()> {
+        let previous = self.tab;
+        self.park_tab();
+        self.tab += 1;
+        self.tabs.insert(self.tab, Tab::default());
+        self.focused = 0;
+        let res = self.open(title, url);
+        // nothing to show in it, like after a download
+        if self.views.is_empty() {
+            self.tabs.remove(self.tab);
+            self.tab = previous;
+            self.unpark_tab();
+        }
+        self.dirty = true;
+        res
+    }
+
+    /// Focus another tab.
+    fn switch_tab(&mut self, tab: usize) -> Result<()> {
+        if tab == self.tab || tab >= self.tabs.len() {
+            return Ok(());
+        }
+        self.park_tab();
+        self.tab = tab;
+        self.unpark_tab();
+        self.dirty = true;
+        self.load_focused()
+    }
+
+    /// Close the focused tab and focus the one after it, or the one
+    /// before it if it was the last.
+    fn close_tab(&mut self) -> Result<()> {
+        if self.tabs.len() == 1 {
+            return Err(error!("Can't close the last tab."));
+        }
+        self.tree_page = None;
+        self.tabs.remove(self.tab);
+        self.tab = self.tab.min(self.tabs.len() - 1);
+        self.unpark_tab();
+        self.dirty = true;
+        self.load_focused()
+    }
+
+    /// Show the open tabs above a prompt and switch to the one picked,
+    /// by number or with the arrow keys.
+    fn list_tabs(&mut self) -> Result<()> {
+        let choices: Vec<String> = (0..self.tabs.len())
+            .map(|t| {
+                let url = self.tab_url(t);
+                let title = self.titles.get(url).map_or(url, String::as_str);
+                format!("{}. {}  {}", t + 1, title, url)
+            })
+            .collect();
+        let (cols, rows) = (self.cols() as usize, self.rows() as usize);
+        let height = FINDER_ROWS.min(rows.saturating_sub(1));
+        let mut selected = self.tab;
+        let picked = self.prompt_with("Go to tab: ", "", |input, key| {
+            match key {
+                None => match input.trim().parse::<usize>() {
+                    Ok(n) if n > 0 && n <= choices.len() => selected = n - 1,
+                    _ => {}
+                },
+                Some(Key::Up) | Some(Key::Ctrl('p')) => selected = selected.saturating_sub(1),
+                Some(Key::Down) | Some(Key::Ctrl('n')) if selected + 1 < choices.len() => {
+                    selected += 1
+                }
+                _ => {}
+            }
+            render_choices(&choices, selected, (cols, rows), height)
+        });
+        self.dirty = true;
+        if picked.is_some() {
+            self.switch_tab(selected)?;
+        }
+        Ok(())
+    }
+
+    /// URL of the page a tab is showing.
+    fn tab_url(&self, tab: usize) -> &str {
+        let (views, focused) = if tab == self.tab {
+            (&self.views, self.focused)
+        } else {
+            (&self.tabs[tab].views, self.tabs[tab].focused)
+        };
+        views.get(focused).map_or("", |view| view.url())
+    }
+
+    /// Move the focused tab's Views into its slot in `tabs`.
+    fn park_tab(&mut self) {
+        self.close_tree_page();
+        self.tabs[self.tab] = Tab {
+            views: std::mem::take(&mut self.views),
+            focused: self.focused,
+            tree: std::mem::take(&mut self.tree),
+        };
+    }
+
+    /// Move the focused tab's Views out of its slot in `tabs`.
+    fn unpark_tab(&mut self) {
+        let tab = std::mem::take(&mut self.tabs[self.tab]);
+        self.views = tab.views;
+        self.focused = tab.focused;
+        self.tree = tab.tree;
+    }
+
+    /// Go to a page in the history tree, making its branch the one
+    /// you go back and forward in.
+    fn jump(&mut self, node: usize) -> Result<()> {
+        self.close_tree_page();
+        self.dirty = true;
+        match self.tree.jump(&mut self.views, node) {
+            Some(focused) => self.focused = focused,
+            None => return Err(error!("Page not in history tree: {}", node)),
+        }
+        self.unload_branches();
+        self.load_focused()
+    }
+
+    /// Show the history tree page. It's shown in place of the tab's
+    /// Views rather than added to them, so it never shows up in the
+    /// tree or moves pages to a new branch.
+    fn open_tree_page(&mut self) -> Result<()> {
+        if self.tree_page.is_some() {
+            return Ok(());
+        }
+        let raw = self.tree_menu();
+        let view = Box::new(Menu::from(TREE_URL, raw, &self.config, false));
+        let views = std::mem::replace(&mut self.views, vec![view]);
+        self.tree_page = Some((views, self.focused));
+        self.focused = 0;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Put the tab's Views back where the history tree page was.
+    /// Returns true if it was open.
+    fn close_tree_page(&mut self) -> bool {
+        match self.tree_page.take() {
+            Some((views, focused)) => {
+                self.views = views;
+                self.focused = focused;
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Swap the Views on other branches of the history tree for
+    /// unloaded ones, so only the branch you're on is kept in memory.
+    /// Jumping back to one loads it again. Link check reports are
+    /// kept, since they can't be.
+    fn unload_branches(&mut self) {
+        let titles = &self.titles;
+        let others = self.tree.others_mut();
+        for view in others.filter(|v| v.is_loaded() && v.url() != LINKCHECK_URL) {
+            let url = view.url().to_string();
+            let page = session::Page {
+                title: titles.get(&url).cloned().unwrap_or_else(|| url.clone()),
+                url,
+                scroll: view.scroll(),
+                link: view.selected_link(),
+                wide: view.wide(),
+            };
+            *view = Box::new(Lazy::from(page));
+        }
+    }
+
+    /// Gophermap of the focused tab's history tree, indented by how
+    /// many pages deep each one is.
+    fn tree_menu(&self) -> String {
+        let current = self.tree.node(self.focused);
+        let mut out = String::from("i\r\ni** history tree **\r\ni\r\n");
+        for (node, depth) in self.tree.walk() {
+            if let Some(view) = self.tree.get(&self.views, node) {
+                let url = view.url();
+                let title = self.titles.get(url).map_or(url, String::as_str);
+                let label = format!(
+                    "{}{}{}",
+                    "  ".repeat(depth),
+                    title,
+                    if Some(node) == current {
+                        " (current)"
+                    } else {
+                        ""
+                    }
+                );
+                let link = format!("{}/{}", TREE_URL, node);
+                out.push_str(&phetchdir::menu_line(&label, &link));
+                out.push_str("\r\n");
+            }
+        }
+        out
+    }
+
     /// Print the current view to the screen in rendered form.
     pub fn draw(&mut self) -> Result<()> {
+        self.update_preview();
+        self.update_prefetch();
         let status = self.render_status();
         if self.dirty {
             let screen = self.render()?;
@@ -148,13 +536,83 @@ impl UI {
             out.flush()?;
             self.dirty = false;
         } else {
+            let pane = if self.preview.changed() {
+                self.render_preview()
+            } else {
+                None
+            };
             let mut out = self.out.borrow_mut();
+            out.write_all(pane.unwrap_or_default().as_ref())?;
             out.write_all(status.as_ref())?;
             out.flush()?;
         }
         Ok(())
     }
 
+    /// Width of the menu when the screen is split, or None if it's
+    /// not: split mode is off, the screen is too narrow, or the
+    /// focused View isn't a menu.
+    fn split(&self) -> Option<usize> {
+        let cols = self.size.0;
+        let view = self.views.get(self.focused)?;
+        if self.config.split && cols >= preview::MIN_COLS && is_menu_url(view.url()) {
+            Some(cols / 2)
+        } else {
+            None
+        }
+    }
+
+    /// Point the preview at the selected link, if the screen is split.
+    fn update_preview(&mut self) {
+        let url = match self.split() {
+            Some(_) => self.views.get(self.focused).and_then(|v| v.selected_url()),
+            None => None,
+        };
+        self.preview.show(url.as_deref());
+        self.preview.update(&self.prefetch);
+    }
+
+    /// Tell the prefetcher what's on screen.
+    fn update_prefetch(&mut self) {
+        let view = match self.views.get(self.focused) {
+            Some(view) if view.is_loaded() => view,
+            _ => return,
+        };
+        let selected = if self.config.prefetch || self.split().is_some() {
+            view.selected_url()
+        } else {
+            None
+        };
+        self.prefetch.focus(view.url(), view.raw(), selected);
+    }
+
+    /// Draw the preview pane next to the menu, if the screen is split.
+    fn render_preview(&mut self) -> Option<String> {
+        let left = self.split()?;
+        let (cols, rows) = self.size;
+        Some(self.preview.render(left + 1, (cols - left, rows)))
+    }
+
+    /// Wait for a keypress. If it doesn't come soon, prefetch the
+    /// selected link. While a preview is loading, check on it every
+    /// so often and draw it as soon as it's ready.
+    fn wait_for_input(&mut self) -> Result<()> {
+        if self.prefetch.is_waiting() && !stdin_ready(prefetch::REST_MS) {
+            self.prefetch.rested();
+        }
+        while self.preview.is_loading() && !stdin_ready(PREVIEW_POLL_MS) {
+            self.preview.update(&self.prefetch);
+            if self.preview.changed() {
+                if let Some(pane) = self.render_preview() {
+                    let mut out = self.out.borrow_mut();
+                    write!(out, "{}{}", pane, terminal::HideCursor)?;
+                    out.flush()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Accept user input and update data.
     pub fn update(&mut self) {
         let action = self.process_view_input();
@@ -175,25 +633,72 @@ impl UI {
             }
         }
 
+        // history tree, ex: gopher://phetch/1/tree/3
+        if url == TREE_URL {
+            return self.open_tree_page();
+        }
+        if let Some(node) = url.strip_prefix(TREE_URL).and_then(|n| n.strip_prefix('/')) {
+            let node = node
+                .parse()
+                .map_err(|_| error!("Bad history tree URL: {}", url))?;
+            return self.jump(node);
+        }
+
         // telnet
         if url.starts_with("telnet://") {
             return self.telnet(url);
         }
 
+        // local files. only pages on disk and phetch's own pages can
+        // link to them without asking, so a server can't open them.
+        if url.starts_with("file://") {
+            let from_local = match self.views.get(self.focused) {
+                Some(view) => {
+                    view.url().starts_with("file://") || view.url().starts_with("gopher://phetch")
+                }
+                None => true,
+            };
+            let path = gophermap::file_path(url);
+            if !from_local {
+                self.dirty = true;
+                let question = format!("Open local file? {}", path.to_string_lossy());
+                if !self.confirm(&question) {
+                    return Ok(());
+                }
+            }
+            // local files phetch can't display
+            if let Some(typ) = handler_type(url) {
+                self.dirty = true;
+                return match self.handler_for(typ) {
+                    Some(cmd) => self.run_handler(&cmd, url, &path.to_string_lossy()),
+                    None => utils::open_external(url),
+                };
+            }
+        }
+
         // non-gopher URL
-        if url.contains("://") && !url.starts_with("gopher://") {
+        if url.contains("://") && !url.starts_with("gopher://") && !url.starts_with("file://") {
             self.dirty = true;
             return if self.confirm(&format!("Open external URL? {}", url)) {
-                utils::open_external(url)
+                match handler_type(url).and_then(|typ| self.handler_for(typ)) {
+                    Some(cmd) => self.run_handler(&cmd, url, ""),
+                    None => utils::open_external(url),
+                }
             } else {
                 Ok(())
             };
         }
 
         // binary downloads
-        let typ = gopher::type_for_url(url);
-        if typ.is_download() {
+        if let Some(typ) = handler_type(url) {
             self.dirty = true;
+            if let Some(cmd) = self.handler_for(typ) {
+                return if self.confirm(&format!("Open {} with {}?", url, cmd)) {
+                    self.open_with(&cmd, url)
+                } else {
+                    Ok(())
+                };
+            }
             return if self.confirm(&format!("Download {}?", url)) {
                 self.download(url)
             } else {
@@ -201,6 +706,9 @@ impl UI {
             };
         }
 
+        if title != url {
+            self.titles.insert(url.to_string(), title.to_string());
+        }
         self.load(title, url).and_then(|view| {
             self.add_view(view);
             Ok(())
@@ -228,37 +736,369 @@ impl UI {
         })
     }
 
+    /// The user's configured handler command for a Gopher type, if any.
+    fn handler_for(&self, typ: Type) -> Option<String> {
+        let key = if typ.is_telnet() {
+            "telnet".to_string()
+        } else {
+            typ.to_char()?.to_string()
+        };
+        self.config.handlers.get(&key).cloned()
+    }
+
+    /// Open a Gopher item with a handler command. If the command
+    /// wants a file (`%f`), the item is downloaded to a temp file first.
+    fn open_with(&mut self, cmd: &str, url: &str) -> Result<()> {
+        if !cmd.contains("%f") {
+            return self.run_handler(cmd, url, "");
+        }
+        let thread_url = url.to_string();
+        let (tls, tor) = (self.config.tls, self.config.tor);
+        let path = self.spinner(&format!("Downloading {}", url), move || {
+            gopher::download_url_to_temp(&thread_url, tls, tor)
+        })??;
+        let res = self.run_handler(cmd, url, &path.to_string_lossy());
+        fs::remove_file(&path).ok();
+        res
+    }
+
+    /// Run a handler command for a URL, with the terminal suspended.
+    fn run_handler(&mut self, cmd: &str, url: &str, file: &str) -> Result<()> {
+        if file.is_empty() && cmd.contains("%f") {
+            return Err(error!("Can't open {} with {}: no file", url, cmd));
+        }
+        let gopher::Url { host, port, .. } = gopher::parse_url(url);
+        let args = utils::handler_command(cmd, file, url, host, port);
+        if args.is_empty() {
+            return Err(error!("Empty handler for {}", url));
+        }
+        self.run_command(&args[0], &args[1..])
+            .map_err(|e| error!("Handler error: {}: {}", args[0], e))
+    }
+
     /// Fetches a URL and returns a View for its content.
     fn load(&mut self, title: &str, url: &str) -> Result<Box<dyn View>> {
         // on-line help
-        if url.starts_with("gopher://phetch/") {
+        if url == "gopher://phetch" || url.starts_with("gopher://phetch/") {
             return self.load_internal(url);
         }
+        // local files
+        if url.starts_with("file://") {
+            return self.load_file(url);
+        }
         // record history urls
-        let hurl = url.to_string();
-        let hname = title.to_string();
-        thread::spawn(move || history::save(&hname, &hurl));
+        if !self.config.private {
+            let hurl = url.to_string();
+            let hname = title.to_string();
+            let hmax = self.config.history_max;
+            thread::spawn(move || history::save(&hname, &hurl, hmax));
+        }
         // request thread
         let thread_url = url.to_string();
         let (tls, tor) = (self.config.tls, self.config.tor);
-        // don't spin on first ever request
-        let (tls, res) = if self.views.is_empty() {
-            gopher::fetch_url(&thread_url, tls, tor)?
+        let prefetched = self.prefetch.take(url);
+        let (tls, body) = if let Some(page) = prefetched {
+            page
+        } else if self.views.is_empty() && self.tabs.len() == 1 {
+            // don't spin on first ever request
+            gopher::fetch_url_bytes(&thread_url, tls, tor)?
         } else {
-            self.spinner("", move || gopher::fetch_url(&thread_url, tls, tor))??
+            self.spinner("", move || gopher::fetch_url_bytes(&thread_url, tls, tor))??
+        };
+        // fall back to the server's charset if it's not UTF-8
+        let res = if std::str::from_utf8(&body).is_ok() {
+            gopher::decode(&body, None)
+        } else {
+            let u = gopher::parse_url(url);
+            let charset = self
+                .caps_for(u.host, u.port)
+                .and_then(|c| c.charset().map(String::from));
+            gopher::decode(&body, charset.as_deref())
         };
         let typ = gopher::type_for_url(&url);
         match typ {
             Type::Menu | Type::Search => Ok(Box::new(Menu::from(url, res, &self.config, tls))),
-            Type::Text | Type::HTML => Ok(Box::new(Text::from(url, res, &self.config, tls))),
+            Type::HTML => {
+                let map = html::to_gophermap(url, &res);
+                Ok(Box::new(Menu::from(url, map, &self.config, tls)))
+            }
+            Type::Text => Ok(Box::new(Text::from(url, res, &self.config, tls))),
             _ => Err(error!("Unsupported Gopher Response: {:?}", typ)),
         }
     }
 
+    /// Probe every link on the current page and show a report of
+    /// which are dead.
+    fn check_links(&mut self) -> Result<()> {
+        let (url, raw) = match self.views.get(self.focused) {
+            Some(view) => (view.url().to_string(), view.raw().to_string()),
+            None => return Ok(()),
+        };
+        if !is_menu_url(&url) {
+            return Err(error!("Can only check links in menus"));
+        }
+
+        let links = linkcheck::links(&raw);
+        if links.is_empty() {
+            self.set_status("No links to check.");
+            return Ok(());
+        }
+        let (tls, tor) = (self.config.tls, self.config.tor);
+        let label = format!("Checking {} links", links.len());
+        let checks = self.spinner(&label, move || linkcheck::check(links, tls, tor))?;
+        self.set_status(&linkcheck::summary(&checks));
+        let report = linkcheck::report(&url, &checks);
+        let menu = Menu::from(LINKCHECK_URL, report, &self.config, false);
+        self.add_view(Box::new(menu));
+        Ok(())
+    }
+
+    /// The bookmarks shown on the current page and the number of the
+    /// selected one, from 1. Errors if this isn't a bookmarks page.
+    fn selected_bookmark(&self, action: &str) -> Result<(Bookmarks, usize)> {
+        let view = self.views.get(self.focused);
+        let folder = match view.and_then(|v| bookmarks::folder_for_url(v.url())) {
+            Some(folder) => folder,
+            None => {
+                return Err(error!(
+                    "Can only {} bookmarks on the bookmarks page",
+                    action
+                ))
+            }
+        };
+        let marks = Bookmarks::load_folder(folder.as_deref())?;
+        let nav = bookmarks::nav_links(marks.folder()).len();
+        match view.and_then(|v| v.selected_link()) {
+            Some(link) if link >= nav && link - nav < marks.len() => Ok((marks, link - nav + 1)),
+            _ => Err(error!("Select a bookmark to {}", action)),
+        }
+    }
+
+    /// Delete the selected bookmark, after asking.
+    fn delete_bookmark(&mut self) -> Result<()> {
+        let (mut marks, n) = self.selected_bookmark("delete")?;
+        let label = marks.list().remove(n - 1).label;
+        self.dirty = true;
+        if !self.confirm(&format!("Delete bookmark #{}: {}?", n, label)) {
+            return Ok(());
+        }
+        marks.remove(n)?;
+        marks.save()?;
+        self.reload_bookmarks(n.min(marks.len()))?;
+        self.set_status(&format!("Deleted bookmark: {}", label));
+        Ok(())
+    }
+
+    /// Move the selected bookmark to a new position.
+    fn move_bookmark(&mut self) -> Result<()> {
+        let (mut marks, n) = self.selected_bookmark("move")?;
+        let to = match self.prompt(&format!("Move bookmark #{} to: ", n), "") {
+            Some(to) => to
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| error!("Not a number: {}", to))?,
+            None => return Ok(()),
+        };
+        marks.move_to(n, to)?;
+        marks.save()?;
+        self.reload_bookmarks(to)?;
+        self.set_status(&format!("Moved bookmark #{} to #{}", n, to));
+        Ok(())
+    }
+
+    /// Change the label of the selected bookmark.
+    fn rename_bookmark(&mut self) -> Result<()> {
+        let (mut marks, n) = self.selected_bookmark("rename")?;
+        let label = marks.list().remove(n - 1).label;
+        let new_label = match self.prompt(&format!("Rename bookmark #{}: ", n), &label) {
+            Some(l) if !l.trim().is_empty() && l != label => l,
+            _ => return Ok(()),
+        };
+        marks.rename(n, new_label.trim())?;
+        marks.save()?;
+        self.reload_bookmarks(n)?;
+        self.set_status(&format!("Renamed bookmark: {}", new_label.trim()));
+        Ok(())
+    }
+
+    /// Move the selected bookmark into a folder. An empty name moves
+    /// it back to the main bookmarks page.
+    fn file_bookmark(&mut self) -> Result<()> {
+        let (mut marks, n) = self.selected_bookmark("file")?;
+        let current = marks.folder().unwrap_or("").to_string();
+        let name = match self.prompt("Move to folder (empty for none): ", &current) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let folder = if name.trim().is_empty() {
+            None
+        } else {
+            Some(bookmarks::folder_name(&name)?)
+        };
+        let mut dest = Bookmarks::load_folder(folder.as_deref())?;
+        let bookmark = marks.move_to_folder(n, &mut dest)?;
+        dest.save()?;
+        marks.save()?;
+        self.reload_bookmarks(n.min(marks.len()))?;
+        let msg = match folder {
+            Some(name) => format!("Moved {} to {}/", bookmark.label, name),
+            None => format!("Moved {} to bookmarks", bookmark.label),
+        };
+        self.set_status(&msg);
+        Ok(())
+    }
+
+    /// Bookmark the current page, asking for a label. The page's
+    /// title is suggested.
+    fn save_bookmark(&mut self) -> Result<()> {
+        let url = match self.views.get(self.focused) {
+            Some(view) => view.url().to_string(),
+            None => return Ok(()),
+        };
+        let title = self
+            .titles
+            .get(&url)
+            .cloned()
+            .unwrap_or_else(|| bookmarks::default_label(&url));
+        let label = match self.prompt("Bookmark label: ", &title) {
+            Some(l) if !l.trim().is_empty() => l,
+            _ => return Ok(()),
+        };
+        bookmarks::save(label.trim(), &url).map_err(|e| error!("Save failed: {}", e))?;
+        self.set_status(&format!("Saved bookmark: {}", label.trim()));
+        Ok(())
+    }
+
+    /// Replace the bookmarks page after editing, keeping the cursor
+    /// on bookmark number `n`.
+    fn reload_bookmarks(&mut self, n: usize) -> Result<()> {
+        let url = match self.views.get(self.focused) {
+            Some(view) => view.url().to_string(),
+            None => return Ok(()),
+        };
+        let folder = bookmarks::folder_for_url(&url).unwrap_or(None);
+        let link = bookmarks::nav_links(folder.as_deref()).len() + n.saturating_sub(1);
+        let mut view = self.load("Bookmarks", &url)?;
+        view.term_size(self.size.0, self.size.1);
+        view.set_selected_link(link);
+        if let Some(focused) = self.views.get_mut(self.focused) {
+            *focused = view;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Get a View for a local file or directory, ex: file:///home/me/gopher
+    fn load_file(&mut self, url: &str) -> Result<Box<dyn View>> {
+        let (typ, raw) = gophermap::load_url(url)?;
+        Ok(match typ {
+            Type::Menu => Box::new(Menu::from(url, raw, &self.config, false)),
+            Type::HTML => {
+                let map = html::to_gophermap(url, &raw);
+                Box::new(Menu::from(url, map, &self.config, false))
+            }
+            _ => Box::new(Text::from(url, raw, &self.config, false)),
+        })
+    }
+
+    /// Open the menu containing the current page. Uses the server's
+    /// caps.txt path delimiter, if it has one.
+    fn open_parent(&mut self) -> Result<()> {
+        let url = match self.views.get(self.focused) {
+            Some(view) => view.url().to_string(),
+            None => return Ok(()),
+        };
+        if url.starts_with("file://") {
+            let path = gophermap::file_path(&url);
+            return match path.parent() {
+                Some(dir) => {
+                    let dir = gophermap::file_url(dir);
+                    self.open(&dir, &dir)
+                }
+                None => Err(error!("Already at the top")),
+            };
+        }
+
+        let u = gopher::parse_url(&url);
+        let delimiter = if u.host.is_empty() || u.host == "phetch" {
+            "/".to_string()
+        } else {
+            let (host, port) = (u.host.to_string(), u.port.to_string());
+            self.caps_for(&host, &port)
+                .map(|c| c.path_delimiter().to_string())
+                .unwrap_or_else(|| "/".into())
+        };
+        match gopher::parent_url(&url, &delimiter) {
+            Some(parent) => self.open(&parent, &parent),
+            None => Err(error!("Already at the top")),
+        }
+    }
+
+    /// Save the current page to a file. The extension picks the
+    /// format: .md, .html, .gph, or plain text.
+    fn export(&mut self) -> Result<()> {
+        let (url, raw) = match self.views.get(self.focused) {
+            Some(view) => (view.url().to_string(), view.raw().to_string()),
+            None => return Ok(()),
+        };
+        let name = gopher::download_filename(&url)
+            .ok()
+            .filter(|n| !n.is_empty() && !n.contains(':'))
+            .unwrap_or_else(|| gopher::parse_url(&url).host.to_string());
+        let name = match Path::new(&name).extension() {
+            Some(_) => name,
+            None => format!("{}.txt", name),
+        };
+        let path = match self.prompt("Export to (.txt .md .html .gph): ", &name) {
+            Some(path) if !path.is_empty() => path,
+            _ => return Ok(()),
+        };
+        let file = utils::expand_home(&path);
+        let menu = is_menu_url(&url);
+        let format = match export::save(&file, &url, &raw, menu, false) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if !self.confirm(&format!("Overwrite {}?", path)) {
+                    return Ok(());
+                }
+                export::save(&file, &url, &raw, menu, true)
+            }
+            res => res,
+        }
+        .map_err(|e| error!("Export failed: {}", e))?;
+        self.set_status(&format!("Exported {:?} to {}", format, path));
+        Ok(())
+    }
+
+    /// A server's caps.txt, fetched the first time it's needed.
+    fn caps_for(&mut self, host: &str, port: &str) -> Option<&Caps> {
+        let key = format!("{}:{}", host, port);
+        if !self.caps.contains_key(&key) {
+            let (host, port) = (host.to_string(), port.to_string());
+            let (tls, tor) = (self.config.tls, self.config.tor);
+            let caps = self
+                .spinner("Fetching caps.txt", move || {
+                    caps::fetch(&host, &port, tls, tor)
+                })
+                .ok()
+                .and_then(|c| c);
+            self.caps.insert(key.clone(), caps);
+        }
+        self.caps.get(&key).and_then(|c| c.as_ref())
+    }
+
     /// Get Menu for on-line help, home page, etc, ex: gopher://phetch/1/help/types
     fn load_internal(&mut self, url: &str) -> Result<Box<dyn View>> {
+        // server info, ex: gopher://phetch/1/server/sdf.org:70
+        if let Some((_, addr)) = url.split_once("/1/server/") {
+            let mut parts = addr.rsplitn(2, ':');
+            let port = parts.next().unwrap_or("70").to_string();
+            let host = parts.next().unwrap_or("").to_string();
+            let raw = caps::as_raw_menu(&host, &port, self.caps_for(&host, &port));
+            return Ok(Box::new(Menu::from(url, raw, &self.config, false)));
+        }
         if let Some(source) = help::lookup(
-            &url.trim_start_matches("gopher://phetch/")
+            &url.trim_start_matches("gopher://phetch")
+                .trim_start_matches('/')
                 .trim_start_matches("1/"),
         ) {
             Ok(Box::new(Menu::from(url, source, &self.config, false)))
@@ -325,10 +1165,13 @@ impl UI {
         // TODO: only get size on SIGWINCH
         if let Ok((cols, rows)) = terminal_size() {
             self.term_size(cols as usize, rows as usize);
+            let split = self.split();
             if !self.views.is_empty() && self.focused < self.views.len() {
                 if let Some(view) = self.views.get_mut(self.focused) {
-                    view.term_size(cols as usize, rows as usize);
-                    return Ok(view.render());
+                    view.term_size(split.unwrap_or(cols as usize), rows as usize);
+                    let screen = view.render();
+                    let pane = self.render_preview().unwrap_or_default();
+                    return Ok(screen + &pane);
                 }
             }
             Err(error!(
@@ -369,14 +1212,58 @@ impl UI {
         None
     }
 
+    /// Render the private mode indicator, left of the TLS or Tor one.
+    fn render_private_status(&self) -> Option<String> {
+        if !self.config.private {
+            return None;
+        }
+        let conn = match self.views.get(self.focused) {
+            Some(view) => view.is_tls() || view.is_tor(),
+            None => false,
+        };
+        let status = color_string!("PRIV", Bold, White, BlackBG);
+        Some(format!(
+            "{}{}",
+            terminal::Goto(self.cols() - if conn { 8 } else { 4 }, self.rows()),
+            if self.config.emoji { "🙈" } else { &status },
+        ))
+    }
+
+    /// Render which tab is focused, left of the other indicators.
+    /// Only shown when there's more than one.
+    fn render_tab_status(&self) -> Option<String> {
+        if self.tabs.len() < 2 {
+            return None;
+        }
+        let conn = match self.views.get(self.focused) {
+            Some(view) => view.is_tls() || view.is_tor(),
+            None => false,
+        };
+        let label = format!("[{}/{}]", self.tab + 1, self.tabs.len());
+        let mut right = label.len() as u16;
+        if conn {
+            right += 4;
+        }
+        if self.config.private {
+            right += 5;
+        }
+        Some(format!(
+            "{}{}",
+            terminal::Goto(self.cols().saturating_sub(right), self.rows()),
+            color_string!(label, Grey),
+        ))
+    }
+
     /// Render the status line.
     fn render_status(&self) -> String {
         format!(
-            "{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}{}",
             terminal::HideCursor,
             terminal::Goto(1, self.rows()),
             terminal::ClearCurrentLine,
             self.status,
+            self.render_tab_status().unwrap_or_else(|| "".into()),
+            self.render_private_status().unwrap_or_else(|| "".into()),
             self.render_conn_status().unwrap_or_else(|| "".into()),
             color::Reset,
         )
@@ -385,13 +1272,10 @@ impl UI {
     /// Add a View to the app's currently opened Views.
     fn add_view(&mut self, view: Box<dyn View>) {
         self.dirty = true;
-        if !self.views.is_empty() && self.focused < self.views.len() - 1 {
-            self.views.truncate(self.focused + 1);
-        }
-        self.views.push(view);
-        if self.views.len() > 1 {
-            self.focused += 1;
-        }
+        self.close_tree_page();
+        self.tree.push(&mut self.views, self.focused, view);
+        self.focused = self.views.len() - 1;
+        self.unload_branches();
     }
 
     /// Ask user to confirm action with ENTER or Y.
@@ -411,7 +1295,7 @@ impl UI {
         .expect(ERR_STDOUT);
         out.flush().expect(ERR_STDOUT);
 
-        if let Some(Ok(key)) = stdin().keys().next() {
+        if let Some(Ok(key)) = Keyboard.keys().next() {
             match key {
                 Key::Char('\n') => true,
                 Key::Char('y') | Key::Char('Y') => true,
@@ -424,14 +1308,26 @@ impl UI {
 
     /// Prompt user for input and return what was entered, if anything.
     fn prompt(&self, prompt: &str, value: &str) -> Option<String> {
+        self.prompt_with(prompt, value, |_, _| String::new())
+    }
+
+    /// Prompt user for input, calling `draw` with the input so far
+    /// after every keypress. Keys the prompt doesn't use itself, like
+    /// Up and Down, are passed along too. Whatever `draw` returns is
+    /// printed before the prompt line is redrawn.
+    fn prompt_with<F>(&self, prompt: &str, value: &str, mut draw: F) -> Option<String>
+    where
+        F: FnMut(&str, Option<Key>) -> String,
+    {
         let rows = self.rows();
         let mut input = value.to_string();
 
         let mut out = self.out.borrow_mut();
         write!(
             out,
-            "{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}",
             color::Reset,
+            draw(&input, None),
             terminal::Goto(1, rows),
             terminal::ClearCurrentLine,
             prompt,
@@ -441,8 +1337,8 @@ impl UI {
         .expect(ERR_STDOUT);
         out.flush().expect(ERR_STDOUT);
 
-        for k in stdin().keys() {
-            if let Ok(key) = k {
+        for k in Keyboard.keys() {
+            let extra = if let Ok(key) = k {
                 match key {
                     Key::Char('\n') => {
                         write!(
@@ -455,7 +1351,10 @@ impl UI {
                         out.flush().expect(ERR_STDOUT);
                         return Some(input);
                     }
-                    Key::Char(c) => input.push(c),
+                    Key::Char(c) => {
+                        input.push(c);
+                        draw(&input, None)
+                    }
                     Key::Esc | Key::Ctrl('c') => {
                         write!(
                             out,
@@ -469,16 +1368,18 @@ impl UI {
                     }
                     Key::Backspace | Key::Delete => {
                         input.pop();
+                        draw(&input, None)
                     }
-                    _ => {}
+                    key => draw(&input, Some(key)),
                 }
             } else {
                 break;
-            }
+            };
 
             write!(
                 out,
-                "{}{}{}{}",
+                "{}{}{}{}{}",
+                extra,
                 terminal::Goto(1, rows),
                 terminal::ClearCurrentLine,
                 prompt,
@@ -495,27 +1396,81 @@ impl UI {
         }
     }
 
-    /// Opens an interactive telnet session.
+    /// Search history and bookmarks, showing the best matches above
+    /// the prompt as you type, and open the one picked.
+    fn find(&mut self) -> Result<()> {
+        let items = finder::load();
+        if items.is_empty() {
+            return Err(error!("No history or bookmarks to search."));
+        }
+        let (cols, rows) = (self.cols() as usize, self.rows() as usize);
+        let height = FINDER_ROWS.min(rows.saturating_sub(1));
+        let mut matches = vec![];
+        let mut selected: usize = 0;
+        let query = self.prompt_with("Find: ", "", |input, key| {
+            match key {
+                None => {
+                    matches = finder::search(&items, input);
+                    selected = 0;
+                }
+                Some(Key::Up) | Some(Key::Ctrl('p')) => selected = selected.saturating_sub(1),
+                Some(Key::Down) | Some(Key::Ctrl('n')) if selected + 1 < matches.len() => {
+                    selected += 1
+                }
+                _ => {}
+            }
+            let choices: Vec<String> = matches
+                .iter()
+                .map(|item| format!("{}  {}", item.label, item.url))
+                .collect();
+            render_choices(&choices, selected, (cols, rows), height)
+        });
+        self.dirty = true;
+        if query.is_none() {
+            return Ok(());
+        }
+        match matches.get(selected) {
+            Some(item) => {
+                let (title, url) = (item.label.clone(), item.url.clone());
+                self.open(&title, &url)
+            }
+            None => Err(error!("No matches.")),
+        }
+    }
+
+    /// Opens an interactive telnet session, using the telnet handler
+    /// if one is configured.
     fn telnet(&mut self, url: &str) -> Result<()> {
+        if let Some(cmd) = self.handler_for(Type::Telnet) {
+            return self.run_handler(&cmd, url, "");
+        }
         let gopher::Url { host, port, .. } = gopher::parse_url(url);
+        self.run_command("telnet", &[host, port])
+    }
+
+    /// Runs an external program with the terminal suspended, waiting
+    /// for it to finish.
+    fn run_command<S: AsRef<std::ffi::OsStr>>(&mut self, program: &str, args: &[S]) -> Result<()> {
         let out = self.out.borrow_mut();
         out.suspend_raw_mode().expect(ERR_RAW_MODE);
-        let mut cmd = process::Command::new("telnet")
-            .arg(host)
-            .arg(port)
+        let res = process::Command::new(program)
+            .args(args)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
-            .spawn()?;
-        cmd.wait()?;
+            .spawn()
+            .and_then(|mut cmd| cmd.wait());
         out.activate_raw_mode().expect(ERR_RAW_MODE);
         self.dirty = true; // redraw when finished with session
-        Ok(())
+        res.map(|_| ())
     }
 
     /// Asks the current View to process user input and produce an Action.
     fn process_view_input(&mut self) -> Action {
+        if let Err(e) = self.wait_for_input() {
+            return Action::Error(e.to_string());
+        }
         if let Some(view) = self.views.get_mut(self.focused) {
-            if let Ok(key) = stdin()
+            if let Ok(key) = Keyboard
                 .keys()
                 .nth(0)
                 .ok_or_else(|| Action::Error("stdin.keys() error".to_string()))
@@ -563,32 +1518,88 @@ impl UI {
             }
             Action::Status(s) => self.set_status(&s),
             Action::Open(title, url) => self.open(&title, &url)?,
+            Action::OpenTab(title, url) => self.open_tab(&title, &url)?,
+            Action::Keypress(Key::BackTab) => {
+                let tab = (self.tab + self.tabs.len() - 1) % self.tabs.len();
+                self.switch_tab(tab)?;
+            }
             Action::Prompt(query, fun) => {
                 if let Some(response) = self.prompt(&query, "") {
                     self.process_action(fun(response))?;
                 }
             }
             Action::Keypress(Key::Left) | Action::Keypress(Key::Backspace) => {
-                if self.focused > 0 {
+                if self.close_tree_page() {
+                    self.load_focused()?;
+                } else if self.focused > 0 {
                     self.dirty = true;
                     self.focused -= 1;
+                    self.load_focused()?;
                 }
             }
             Action::Keypress(Key::Right) => {
                 if self.focused < self.views.len() - 1 {
                     self.dirty = true;
                     self.focused += 1;
+                    self.load_focused()?;
                 }
             }
             Action::Keypress(Key::Char(key)) | Action::Keypress(Key::Ctrl(key)) => match key {
                 'a' => self.open("History", "gopher://phetch/1/history")?,
-                'b' => self.open("Bookmarks", "gopher://phetch/1/bookmarks")?,
+                'A' => self.open("History Tree", TREE_URL)?,
+                'b' => self.open("Bookmarks", bookmarks::URL)?,
+                'c' => self.check_links()?,
+                'd' => self.delete_bookmark()?,
+                'e' => self.export()?,
+                'F' => self.file_bookmark()?,
                 'g' => {
                     if let Some(url) = self.prompt("Go to URL: ", "") {
                         self.open(&url, &url)?;
                     }
                 }
                 'h' => self.open("Help", "gopher://phetch/1/help")?,
+                'l' => self.rename_bookmark()?,
+                'm' => self.move_bookmark()?,
+                'o' => self.find()?,
+                'P' => {
+                    self.config.private = !self.config.private;
+                    self.set_status(if self.config.private {
+                        "Private mode on: history won't be saved."
+                    } else {
+                        "Private mode off."
+                    });
+                }
+                'V' => {
+                    self.config.split = !self.config.split;
+                    self.dirty = true;
+                    if self.config.split && self.size.0 < preview::MIN_COLS {
+                        self.set_status(&format!(
+                            "Split view needs a screen at least {} columns wide.",
+                            preview::MIN_COLS
+                        ));
+                    }
+                }
+                'R' => {
+                    if let Some(view) = self.views.get(self.focused) {
+                        let url = view.url().to_string();
+                        match gopher::root_url(&url) {
+                            Some(root) => self.open(&root, &root)?,
+                            None => return Err(error!("No root for {}", url)),
+                        }
+                    }
+                }
+                'U' => self.open_parent()?,
+                'I' => {
+                    if let Some(view) = self.views.get(self.focused) {
+                        let url = view.url();
+                        let u = gopher::parse_url(url);
+                        if u.host.is_empty() || u.host == "phetch" {
+                            return Err(error!("No server info for {}", url));
+                        }
+                        let info = format!("gopher://phetch/1/server/{}:{}", u.host, u.port);
+                        self.open("Server Info", &info)?;
+                    }
+                }
                 'r' => {
                     if let Some(view) = self.views.get(self.focused) {
                         let url = view.url();
@@ -598,18 +1609,7 @@ impl UI {
                         self.add_view(Box::new(text));
                     }
                 }
-                's' => {
-                    if let Some(view) = self.views.get(self.focused) {
-                        let url = view.url();
-                        match bookmarks::save(&url, &url) {
-                            Ok(()) => {
-                                let msg = format!("Saved bookmark: {}", url);
-                                self.set_status(&msg);
-                            }
-                            Err(e) => return Err(error!("Save failed: {}", e)),
-                        }
-                    }
-                }
+                's' => self.save_bookmark()?,
                 'u' => {
                     if let Some(view) = self.views.get(self.focused) {
                         let current_url = view.url();
@@ -636,6 +1636,25 @@ impl UI {
                         self.dirty = true;
                     }
                 }
+                't' => {
+                    // the history tree only shows this tab's pages
+                    let view = self.views.get(self.focused);
+                    if let Some(view) = view.filter(|v| v.url() != TREE_URL) {
+                        let url = view.url().to_string();
+                        let title = self
+                            .titles
+                            .get(&url)
+                            .cloned()
+                            .unwrap_or_else(|| url.clone());
+                        self.open_tab(&title, &url)?;
+                    }
+                }
+                'T' => self.list_tabs()?,
+                '\t' => {
+                    let tab = (self.tab + 1) % self.tabs.len();
+                    self.switch_tab(tab)?;
+                }
+                'x' => self.close_tab()?,
                 'q' => self.running = false,
                 c => return Err(error!("Unknown keypress: {}", c)),
             },
@@ -645,6 +1664,109 @@ impl UI {
     }
 }
 
+/// A list to pick from, drawn in the `height` lines above the prompt
+/// with the selected line marked. Scrolls to keep it in view.
+fn render_choices(
+    choices: &[String],
+    selected: usize,
+    (cols, rows): (usize, usize),
+    height: usize,
+) -> String {
+    let mut out = String::new();
+    let start = (selected + 1).saturating_sub(height);
+    for i in 0..height {
+        let row = (rows - height + i) as u16;
+        out.push_str(&format!(
+            "{}{}",
+            terminal::Goto(1, row),
+            terminal::ClearCurrentLine
+        ));
+        if let Some(choice) = choices.get(start + i) {
+            let line: String = choice.chars().take(cols.saturating_sub(2)).collect();
+            if start + i == selected {
+                out.push_str(&format!("{}* {}{}", color::Bold, line, color::Reset));
+            } else {
+                out.push_str(&format!("  {}", line));
+            }
+        }
+    }
+    out
+}
+
+/// Is the View for this URL a Menu? HTML pages and search results
+/// are shown as menus too.
+fn is_menu_url(url: &str) -> bool {
+    let typ = if url.starts_with("file://") {
+        gophermap::type_for_file(&gophermap::file_path(url))
+    } else {
+        gopher::type_for_url(url)
+    };
+    typ == Type::Menu || typ == Type::Search || typ == Type::HTML
+}
+
+/// The Gopher type whose handler opens this URL, if phetch hands it
+/// off instead of showing it: downloads, telnet, and http(s) links
+/// from `h` items. Other schemes, like mailto:, have no handler.
+fn handler_type(url: &str) -> Option<Type> {
+    if url.starts_with("telnet://") {
+        return Some(Type::Telnet);
+    }
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Some(Type::HTML);
+    }
+    let typ = if url.starts_with("file://") {
+        gophermap::type_for_file(&gophermap::file_path(url))
+    } else if url.starts_with("gopher://") || !url.contains("://") {
+        gopher::type_for_url(url)
+    } else {
+        return None;
+    };
+    Some(typ).filter(|typ| typ.is_download())
+}
+
+/// Reads keypresses straight from stdin, without std's buffering, so
+/// a key that's been typed is never hidden from `stdin_ready()`. Reads
+/// one byte at a time, except that the byte after an Esc comes along
+/// with it so termion can tell Esc from the start of an arrow key.
+struct Keyboard;
+
+impl Read for Keyboard {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let n = read_byte(&mut buf[0])?;
+        if n == 1 && buf[0] == 0x1B && buf.len() > 1 && stdin_ready(0) {
+            return Ok(1 + read_byte(&mut buf[1])?);
+        }
+        Ok(n)
+    }
+}
+
+/// Read a single byte from stdin, waiting for it.
+fn read_byte(byte: &mut u8) -> Result<usize> {
+    loop {
+        let n = unsafe { libc::read(libc::STDIN_FILENO, byte as *mut u8 as *mut libc::c_void, 1) };
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Is there input waiting on stdin? Gives up after `timeout` ms.
+fn stdin_ready(timeout: i32) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, timeout) > 0 }
+}
+
 impl Drop for UI {
     fn drop(&mut self) {
         let mut out = self.out.borrow_mut();
@@ -652,3 +1774,23 @@ impl Drop for UI {
         out.flush().expect(ERR_STDOUT);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handler_type() {
+        assert_eq!(handler_type("https://example.com"), Some(Type::HTML));
+        assert_eq!(handler_type("http://example.com/a.png"), Some(Type::HTML));
+        assert_eq!(handler_type("mailto:me@example.com"), None);
+        assert_eq!(handler_type("ssh://example.com"), None);
+        assert_eq!(handler_type("telnet://bbs.example.com"), Some(Type::Telnet));
+        assert_eq!(handler_type("gopher://host/s/a.mp3"), Some(Type::Sound));
+        assert_eq!(handler_type("example.com/I/cat.jpg"), Some(Type::Image));
+        assert_eq!(handler_type("gopher://example.com/1/"), None);
+        assert_eq!(handler_type("gopher://example.com/0/about.txt"), None);
+        assert_eq!(handler_type("file:///tmp/song.mp3"), Some(Type::Sound));
+        assert_eq!(handler_type("file:///tmp/notes.txt"), None);
+    }
+}
@@ -8,6 +8,8 @@ pub enum Action {
     None,
     /// Open a URL: open(title, url)
     Open(String, String),
+    /// Open a URL in a new tab: open_tab(title, url)
+    OpenTab(String, String),
     /// If the View doesn't know how to react, it returns the keypress.
     Keypress(Key),
     /// Redraw the screen. Can cause a flicker
@@ -46,6 +48,7 @@ impl fmt::Debug for Action {
         match self {
             Action::None => write!(f, "None"),
             Action::Open(title, url) => write!(f, "Open: {}, {}", title, url),
+            Action::OpenTab(title, url) => write!(f, "OpenTab: {}, {}", title, url),
             Action::Keypress(key) => write!(f, "Keypress: {:?}", key),
             Action::Redraw => write!(f, "Redraw"),
             Action::Draw(s) => write!(f, "Draw: {:?}", s),
@@ -15,6 +15,27 @@ pub enum Mode {
     /// Just print raw Gopher response.
     ///   phetch --raw URL
     Raw,
+    /// Check a Gophermap for problems.
+    ///   phetch --lint URL|FILE
+    Lint,
+    /// Download a URL's raw bytes.
+    ///   phetch --download URL
+    Download,
+    /// Edit bookmarks from the command line.
+    ///   phetch bookmarks list|add|rm|mv|export|import
+    Bookmarks,
+    /// Fetch a list of URLs.
+    ///   phetch --batch [FILE]
+    Batch,
+    /// Check the links in a menu.
+    ///   phetch --check-links URL|FILE
+    CheckLinks,
+    /// Save a gopherhole to disk.
+    ///   phetch --mirror URL
+    Mirror,
+    /// Serve a local directory over Gopher.
+    ///   phetch --serve DIR
+    Serve,
     /// Show version info.
     ///   phetch --version
     Version,
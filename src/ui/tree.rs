@@ -0,0 +1,175 @@
+//! Going back and then opening a new page starts a branch, instead
+//! of throwing away the pages you went back from. Together the
+//! branches make a tree of every page opened in a tab.
+//!
+//! The UI keeps the branch you're on as a plain list of Views, so
+//! back and forward work as before. The Tree remembers where each
+//! one came from and holds on to the Views on other branches, which
+//! the UI keeps unloaded so they don't pile up in memory.
+
+use std::collections::HashMap;
+
+/// Every page opened in a tab, and which page it was opened from.
+/// Nodes are numbered in the order they were opened.
+pub(crate) struct Tree<T> {
+    /// Node of each item on the current branch, root first.
+    path: Vec<usize>,
+    /// Parent of each node.
+    parents: Vec<Option<usize>>,
+    /// Items on other branches, by node.
+    others: HashMap<usize, T>,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Tree<T> {
+        Tree {
+            path: vec![],
+            parents: vec![],
+            others: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Tree<T> {
+    /// A tree with a single branch of `len` items.
+    pub(crate) fn branch(len: usize) -> Tree<T> {
+        Tree {
+            path: (0..len).collect(),
+            parents: (0..len).map(|i| i.checked_sub(1)).collect(),
+            others: HashMap::new(),
+        }
+    }
+
+    /// Add an item to the current branch, after the focused one.
+    /// Items after the focused one are moved to their own branch.
+    pub(crate) fn push(&mut self, items: &mut Vec<T>, focused: usize, item: T) {
+        if focused + 1 < items.len() {
+            let nodes = self.path.drain(focused + 1..);
+            self.others.extend(nodes.zip(items.drain(focused + 1..)));
+        }
+        let parent = if items.is_empty() {
+            None
+        } else {
+            self.path.get(focused).copied()
+        };
+        self.path.push(self.parents.len());
+        self.parents.push(parent);
+        items.push(item);
+    }
+
+    /// Make the branch `node` is on the current one, continuing past
+    /// it into its newest children so you can go forward again.
+    /// Returns the index of `node` in `items`, or None if it's not
+    /// in the tree.
+    pub(crate) fn jump(&mut self, items: &mut Vec<T>, node: usize) -> Option<usize> {
+        if node >= self.parents.len() {
+            return None;
+        }
+        self.others.extend(self.path.drain(..).zip(items.drain(..)));
+        let mut path = vec![node];
+        while let Some(parent) = self.parents[path[path.len() - 1]] {
+            path.push(parent);
+        }
+        path.reverse();
+        let focused = path.len() - 1;
+        while let Some(child) = self.children(path[path.len() - 1]).last() {
+            path.push(*child);
+        }
+        for node in &path {
+            items.extend(self.others.remove(node));
+        }
+        self.path = path;
+        Some(focused)
+    }
+
+    /// Node of the item at `index` on the current branch.
+    pub(crate) fn node(&self, index: usize) -> Option<usize> {
+        self.path.get(index).copied()
+    }
+
+    /// Items on branches other than the current one.
+    pub(crate) fn others_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.others.values_mut()
+    }
+
+    /// The item for a node, wherever it is.
+    pub(crate) fn get<'a>(&'a self, items: &'a [T], node: usize) -> Option<&'a T> {
+        match self.path.iter().position(|n| *n == node) {
+            Some(i) => items.get(i),
+            None => self.others.get(&node),
+        }
+    }
+
+    /// Every node with its depth, depth-first, oldest branches first.
+    pub(crate) fn walk(&self) -> Vec<(usize, usize)> {
+        let mut out = vec![];
+        let mut stack: Vec<(usize, usize)> =
+            self.roots().into_iter().rev().map(|n| (n, 0)).collect();
+        while let Some((node, depth)) = stack.pop() {
+            out.push((node, depth));
+            stack.extend(
+                self.children(node)
+                    .into_iter()
+                    .rev()
+                    .map(|n| (n, depth + 1)),
+            );
+        }
+        out
+    }
+
+    /// Nodes without a parent.
+    fn roots(&self) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|n| self.parents[*n].is_none())
+            .collect()
+    }
+
+    /// Nodes opened from `node`, oldest first.
+    fn children(&self, node: usize) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|n| self.parents[*n] == Some(node))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branches() {
+        let mut tree = Tree::default();
+        let mut items = vec![];
+        tree.push(&mut items, 0, "home");
+        tree.push(&mut items, 0, "phlog");
+        tree.push(&mut items, 1, "post");
+        // go back to home, then somewhere else
+        tree.push(&mut items, 0, "about");
+        assert_eq!(items, vec!["home", "about"]);
+        assert_eq!(tree.walk(), vec![(0, 0), (1, 1), (2, 2), (3, 1)]);
+        assert_eq!(tree.get(&items, 2), Some(&"post"));
+
+        // back on the old branch, with the newest page after it
+        assert_eq!(tree.jump(&mut items, 1), Some(1));
+        assert_eq!(items, vec!["home", "phlog", "post"]);
+        assert_eq!(tree.node(2), Some(2));
+        assert_eq!(tree.get(&items, 3), Some(&"about"));
+        assert_eq!(tree.jump(&mut items, 9), None);
+
+        for item in tree.others_mut() {
+            *item = "unloaded";
+        }
+        assert_eq!(tree.get(&items, 3), Some(&"unloaded"));
+        assert_eq!(items, vec!["home", "phlog", "post"]);
+    }
+
+    #[test]
+    fn test_branch() {
+        let mut items = vec!["a", "b", "c"];
+        let mut tree = Tree::branch(items.len());
+        assert_eq!(tree.walk(), vec![(0, 0), (1, 1), (2, 2)]);
+        tree.push(&mut items, 0, "d");
+        assert_eq!(tree.jump(&mut items, 0), Some(0));
+        assert_eq!(items, vec!["a", "d"]);
+    }
+}
@@ -9,6 +9,9 @@ pub trait View: fmt::Display {
     /// Create a String of the current view's state that can be
     /// printed to the screen.
     fn render(&mut self) -> String;
+    /// Has this View's content been fetched? Pages restored from the
+    /// last session aren't until they're focused.
+    fn is_loaded(&self) -> bool;
     /// Was this View's content fetched using TLS?
     fn is_tls(&self) -> bool;
     /// Was this View's content fetched over Tor?
@@ -21,6 +24,16 @@ pub trait View: fmt::Display {
     fn set_wide(&mut self, wide: bool);
     /// In wide mode?
     fn wide(&mut self) -> bool;
+    /// Scroll offset, in lines.
+    fn scroll(&self) -> usize;
+    /// Scroll to a line.
+    fn set_scroll(&mut self, scroll: usize);
+    /// Index of the selected link, if this View has any.
+    fn selected_link(&self) -> Option<usize>;
+    /// Select a link by index, if this View has links.
+    fn set_selected_link(&mut self, link: usize);
+    /// URL of the selected link, if this View has links.
+    fn selected_url(&self) -> Option<String>;
     /// Set the current screen size.
     fn term_size(&mut self, cols: usize, rows: usize);
 }
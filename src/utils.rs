@@ -1,6 +1,7 @@
 //! Helper functions and macros.
 use std::{
     io::{Result, Write},
+    path::PathBuf,
     process::{self, Stdio},
 };
 
@@ -17,8 +18,8 @@ macro_rules! log {
             .open("phetch.log")
         {
             use std::io::prelude::*;
-            file.write($e.as_ref());
-            file.write(b"\n");
+            file.write_all($e.as_ref()).ok();
+            file.write_all(b"\n").ok();
         }
     }
     }};
@@ -54,6 +55,17 @@ pub fn human_bytes(bytes: usize) -> String {
     format!("{}{}", count, tag)
 }
 
+/// Expands a leading `~` in a path the user typed to their home
+/// directory, like a shell would.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(format!("{}{}", home, rest))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
 /// Copies data to the system clipboard, if possible.
 /// Uses `pbcopy` on macOS or `xclip -sel clip` on Linux.
 pub fn copy_to_clipboard(data: &str) -> Result<()> {
@@ -73,6 +85,20 @@ pub fn copy_to_clipboard(data: &str) -> Result<()> {
         .map_err(|e| error!("Clipboard error: {}", e))
 }
 
+/// Builds the program and arguments for a user-configured handler,
+/// like `mpv %f`. `%f` is replaced with the path of the downloaded
+/// file, `%u` with the URL, `%h` with the host, and `%p` with the port.
+pub fn handler_command(cmd: &str, file: &str, url: &str, host: &str, port: &str) -> Vec<String> {
+    cmd.split_whitespace()
+        .map(|arg| {
+            arg.replace("%f", file)
+                .replace("%u", url)
+                .replace("%h", host)
+                .replace("%p", port)
+        })
+        .collect()
+}
+
 /// Used to open non-Gopher URLs.
 /// Runs `open` command on macOS or `xdg-open` on Linux.
 pub fn open_external(url: &str) -> Result<()> {